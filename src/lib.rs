@@ -1,150 +1,3019 @@
 use crate::lsdup::config::Config;
-use crate::lsdup::filevisitor::AllInFileVisitor;
-use crate::lsdup::filevisitor::FileVisitor;
+use crate::lsdup::devino::DevIno;
+use crate::lsdup::filevisitor::{FileVisitor, SharedFileVisitor};
+use crate::lsdup::skip::{self, Skip, SpecialFiles};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::path::Path;
-use std::string::String;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
 
 pub mod lsdup;
 
-pub fn run(config: &Config) -> io::Result<AllInFileVisitor> {
-    let dirs = &config.dirs;
-    let mut dups = AllInFileVisitor::new(&config);
+pub use crate::lsdup::filevisitor::AllInFileVisitor;
+pub use crate::lsdup::hashing::hash_contents_path;
+pub use crate::lsdup::output::{print_action_report, print_results};
 
-    for dir in dirs {
-        if let Err(foo) = visit_dirs(dir, &mut dups, &config) {
-            return Err(foo);
+// Never set, for walks that aren't stopped early.
+static NEVER: AtomicBool = AtomicBool::new(false);
+
+pub fn run(config: &Config) -> io::Result<AllInFileVisitor<'_>> {
+    run_until(config, &NEVER)
+}
+
+/// Walks the configured directories as `run` does, but gives each file to
+/// the visitor from whichever of the `threads` walking threads found it,
+/// rather than gathering them first. Files are visited as soon as they are
+/// found, in no particular order. Directories below the configured ones that
+/// can't be read are skipped.
+///
+/// ```
+/// use lsdup::lsdup::config::Config;
+/// use lsdup::lsdup::filevisitor::SharedFileVisitor;
+/// use std::path::PathBuf;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// struct CountBytes(AtomicU64);
+///
+/// impl SharedFileVisitor for CountBytes {
+///     fn visit(&self, file: PathBuf) {
+///         let len = file.metadata().map_or(0, |meta| meta.len());
+///         self.0.fetch_add(len, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let config = Config {
+///     dirs: vec![PathBuf::from("src")],
+///     threads: 4,
+///     ..Default::default()
+/// };
+/// let count = CountBytes(AtomicU64::new(0));
+/// lsdup::walk_shared(&config, &count).expect("Could not walk src.");
+/// println!("{} bytes", count.0.into_inner());
+/// ```
+pub fn walk_shared(config: &Config, visitor: &dyn SharedFileVisitor) -> io::Result<()> {
+    let walk = WalkState::new(config, &NEVER);
+    for dir in &config.dirs {
+        if is_root_file(dir)? {
+            if walk.first_spelling(dir) {
+                visitor.visit(dir.to_path_buf());
+            } else {
+                skip::log(config.verbosity, dir, Skip::AlreadyVisited);
+            }
+        } else if config.no_recurse {
+            visit_dir_files(dir, &mut SharedVisits(visitor), &walk)?;
+        } else {
+            walk_dirs_shared(dir, config.threads.max(1), visitor, &walk)?;
+        }
+    }
+    Ok(())
+}
+
+// Visits each file with the shared visitor, for the walks that find files
+// one at a time.
+struct SharedVisits<'a>(&'a dyn SharedFileVisitor);
+
+impl<'a> FileVisitor for SharedVisits<'a> {
+    fn visit(&mut self, file: PathBuf) {
+        self.0.visit(file);
+    }
+}
+
+/// As `run`, but once `stop` is set, such as by a Ctrl-C handler, no more
+/// files are walked or hashed, and the duplicates found so far are returned.
+/// Whether that happened is told by the visitor's `interrupted`.
+pub fn run_until<'a>(config: &'a Config, stop: &'a AtomicBool) -> io::Result<AllInFileVisitor<'a>> {
+    let mut dups = AllInFileVisitor::new(config);
+    dups.set_stop(stop);
+    if config.precount {
+        // The count has its own walk state, so directories it read aren't
+        // skipped as already visited, and nothing it skipped is counted twice.
+        let mut count = FileCount(0);
+        walk_all_dirs(config, &mut count, &WalkState::new(config, stop))?;
+        dups.set_expected_files(count.0);
+    }
+    let walk = WalkState::new(config, stop);
+
+    let walked = walk_all_dirs(config, &mut dups, &walk);
+    // Clear the progress bar even if the walk failed, so it isn't left on
+    // the terminal above the error.
+    dups.finish();
+    walked?;
+
+    dups.add_broken_symlinks(walk.broken_symlinks.into_inner());
+    dups.add_special_files(walk.special_files.into_inner().unwrap());
+    let mut unreadable_dirs = walk.unreadable_dirs.into_inner().unwrap();
+    // The parallel walk finds them in no particular order.
+    unreadable_dirs.sort_by(|a, b| a.0.cmp(&b.0));
+    dups.add_unreadable_dirs(unreadable_dirs);
+    // With nothing hashed, such as when no two files are the same size, there
+    // are no groups to order.
+    if dups.num_hashed() > 0 {
+        dups.order_groups();
+    }
+
+    Ok(dups)
+}
+
+// Visits every file in each of the configured directories, and each of the
+// files given directly.
+fn walk_all_dirs(config: &Config, dups: &mut dyn FileVisitor, walk: &WalkState) -> io::Result<()> {
+    for dir in &config.dirs {
+        if walk.stopped() || dups.done() {
+            break;
+        }
+        // Files given directly are compared along with the directories.
+        if is_root_file(dir)? {
+            if walk.first_spelling(dir) {
+                dups.visit(dir.to_path_buf());
+            } else {
+                skip::log(config.verbosity, dir, Skip::AlreadyVisited);
+            }
+            continue;
+        }
+        if config.no_recurse {
+            visit_dir_files(dir, dups, walk)?;
+        } else if config.threads > 1 {
+            for file in walk_dirs_parallel(dir, config.threads, walk)? {
+                if walk.stopped() || dups.done() {
+                    break;
+                }
+                dups.visit(file);
+            }
+        } else if config.breadth_first {
+            visit_dirs_breadth_first(dir, dups, walk)?;
+        } else {
+            visit_dirs(dir, dups, walk)?;
+        }
+    }
+    Ok(())
+}
+
+// The path in lower case, to tell paths that only differ by case apart.
+// Names that aren't valid Unicode are kept as they are, since they have no
+// case to fold, and would otherwise all be alike once made valid.
+fn fold_case(path: &Path) -> PathBuf {
+    path.components()
+        .map(|part| match part.as_os_str().to_str() {
+            Some(name) => OsString::from(name.to_lowercase()),
+            None => part.as_os_str().to_os_string(),
+        })
+        .collect()
+}
+
+// True if the given path is a file rather than a directory to walk. The
+// given paths are always followed if they are symlinks, since naming one
+// means it should be scanned; --follow-symlinks only applies to the symlinks
+// found within. Anything else can't be scanned.
+fn is_root_file(path: &Path) -> io::Result<bool> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Err(io::Error::other(format!(
+            "{:?} is not a directory or regular file",
+            path
+        )));
+    }
+    Ok(metadata.is_file())
+}
+
+// Counts the files found, without looking at them.
+struct FileCount(u64);
+
+impl FileVisitor for FileCount {
+    fn visit(&mut self, _file: PathBuf) {
+        self.0 += 1;
+    }
+}
+
+// An entry of a directory that is worth looking at further.
+enum DirItem {
+    Dir(PathBuf),
+    File(PathBuf),
+}
+
+// State of the walk shared by the walking threads: what was skipped along
+// the way, so it can be reported once at the end instead of for every entry,
+// and which directories were already read.
+struct WalkState<'a> {
+    config: &'a Config,
+    broken_symlinks: AtomicU32,
+    special_files: Mutex<SpecialFiles>,
+    // Directories below the given ones that couldn't be read, and why.
+    unreadable_dirs: Mutex<Vec<(PathBuf, io::Error)>>,
+    // Only tracked when following symlinks, since otherwise a directory can
+    // only be reached one way.
+    visited_dirs: Mutex<BTreeSet<DevIno>>,
+    // With --case-fold, the paths of the directories read and the files given
+    // directly, in lowercase.
+    visited_paths: Mutex<HashSet<PathBuf>>,
+    // Set when the walk should stop early, such as on Ctrl-C.
+    stop: &'a AtomicBool,
+}
+
+impl<'a> WalkState<'a> {
+    fn new(config: &'a Config, stop: &'a AtomicBool) -> WalkState<'a> {
+        WalkState {
+            config,
+            broken_symlinks: AtomicU32::new(0),
+            special_files: Mutex::new(SpecialFiles::default()),
+            unreadable_dirs: Mutex::new(Vec::new()),
+            visited_dirs: Mutex::new(BTreeSet::new()),
+            visited_paths: Mutex::new(HashSet::new()),
+            stop,
+        }
+    }
+
+    // True once the walk should stop, leaving the rest of the files unvisited.
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // True if the directory hasn't been read yet. A symlink to one of its own
+    // ancestors, or to a directory that was already read some other way,
+    // would otherwise be walked forever or have its files found twice.
+    fn first_visit(&self, dir: &Path) -> bool {
+        if !self.first_spelling(dir) {
+            return false;
+        }
+        // Without inode numbers (on Windows), directories can't be told apart.
+        if !self.config.follow_symlinks || !cfg!(target_family = "unix") {
+            return true;
+        }
+        match fs::metadata(dir) {
+            Ok(meta) => self
+                .visited_dirs
+                .lock()
+                .unwrap()
+                .insert(DevIno::from(&meta)),
+            Err(_) => true,
+        }
+    }
+
+    // True unless, with --case-fold, the path was already reached spelled
+    // with other case. Without inode numbers to go by, or when the same
+    // directory is given twice, a case insensitive filesystem would otherwise
+    // have its files found twice.
+    fn first_spelling(&self, path: &Path) -> bool {
+        if !self.config.case_fold {
+            return true;
+        }
+        self.visited_paths.lock().unwrap().insert(fold_case(path))
+    }
+
+    // Notes a directory that couldn't be read, such as for lack of
+    // permission. They are only listed at the end, when verbose, so they
+    // don't interrupt the progress display.
+    fn skip_unreadable_dir(&self, dir: PathBuf, e: io::Error) {
+        self.unreadable_dirs.lock().unwrap().push((dir, e));
+    }
+
+    // Counts a fifo, socket, device, or the like, so that trees such as /dev
+    // aren't listed file by file unless verbose.
+    fn skip_special_file(&self, path: &Path, metadata: &fs::Metadata) {
+        self.special_files.lock().unwrap().add(metadata);
+        skip::log(self.config.verbosity, path, Skip::NotRegular);
+    }
+
+    // Counts a symlink whose target doesn't exist. Each one is only listed
+    // when verbose.
+    fn skip_broken_symlink(&self, path: &Path) {
+        self.broken_symlinks.fetch_add(1, Ordering::Relaxed);
+        skip::log(self.config.verbosity, path, Skip::BrokenSymlink);
+    }
+}
+
+// Reads the entries of the directory in name order, so that the same tree is
+// always traversed the same way regardless of how the filesystem lists it.
+// Anything that isn't a directory or a regular file is skipped, as are
+// symlinks unless they are being followed.
+fn read_dir_items(dir: &Path, walk: &WalkState) -> io::Result<Vec<DirItem>> {
+    if !walk.first_visit(dir) {
+        skip::log(walk.config.verbosity, dir, Skip::AlreadyVisited);
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            // Entries removed while the directory is read are simply gone.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => eprintln!("Skipping entry in directory {:?}.\nReason: {}", dir, e),
+        }
+    }
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut items = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path = entry.path();
+        let metadata = if walk.config.follow_symlinks {
+            // Following a broken symlink fails, so look at the link itself to
+            // tell that apart from other errors.
+            fs::metadata(&path).or_else(|_| entry.metadata())
+        } else {
+            entry.metadata()
+        };
+        match metadata {
+            Ok(metadata) if walk.config.skip_hidden && is_hidden(&path, &metadata) => {
+                skip::log(walk.config.verbosity, &path, Skip::Hidden);
+            }
+            Ok(_) if skip::is_excluded(&path, walk.config) => {
+                skip::log(walk.config.verbosity, &path, Skip::Excluded);
+            }
+            Ok(metadata) => {
+                // Only visit real (non-symlinked) directories, unless following symlinks
+                if path.is_dir() && metadata.is_dir() {
+                    items.push(DirItem::Dir(path));
+                } else if metadata.is_file() {
+                    items.push(DirItem::File(path));
+                } else if metadata.file_type().is_symlink() && fs::metadata(&path).is_err() {
+                    walk.skip_broken_symlink(&path);
+                } else if metadata.file_type().is_symlink() {
+                    skip::log(walk.config.verbosity, &path, Skip::Symlink);
+                } else {
+                    walk.skip_special_file(&path, &metadata);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                skip::log(walk.config.verbosity, &path, Skip::Vanished);
+            }
+            Err(e) => eprintln!("Skipping {:?}.\nReason: {}", entry, e),
+        }
+    }
+    Ok(items)
+}
+
+// True if the file or directory is hidden: its name starts with a dot.
+#[cfg(not(target_family = "windows"))]
+fn is_hidden(path: &Path, _metadata: &fs::Metadata) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+// True if the file or directory has the hidden attribute.
+#[cfg(target_family = "windows")]
+fn is_hidden(_path: &Path, metadata: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+fn visit_dirs(dir: &Path, visitor: &mut dyn FileVisitor, walk: &WalkState) -> io::Result<()> {
+    for item in read_dir_items(dir, walk)? {
+        if walk.stopped() || visitor.done() {
+            break;
+        }
+        match item {
+            DirItem::Dir(path) => {
+                if let Err(e) = visit_dirs(&path, visitor, walk) {
+                    walk.skip_unreadable_dir(path, e);
+                }
+            }
+            DirItem::File(path) => visitor.visit(path),
+        }
+    }
+    Ok(())
+}
+
+// Visits only the files directly within the directory, skipping its
+// subdirectories.
+fn visit_dir_files(dir: &Path, visitor: &mut dyn FileVisitor, walk: &WalkState) -> io::Result<()> {
+    for item in read_dir_items(dir, walk)? {
+        if walk.stopped() || visitor.done() {
+            break;
+        }
+        if let DirItem::File(path) = item {
+            visitor.visit(path);
+        }
+    }
+    Ok(())
+}
+
+// Visits the files of the directory, then those of its subdirectories, and so
+// on, level by level, rather than all of one subdirectory before the next.
+fn visit_dirs_breadth_first(
+    dir: &Path,
+    visitor: &mut dyn FileVisitor,
+    walk: &WalkState,
+) -> io::Result<()> {
+    let mut dirs = VecDeque::new();
+    for item in read_dir_items(dir, walk)? {
+        match item {
+            DirItem::Dir(path) => dirs.push_back(path),
+            DirItem::File(path) => visitor.visit(path),
+        }
+    }
+    while let Some(dir) = dirs.pop_front() {
+        if walk.stopped() || visitor.done() {
+            break;
+        }
+        match read_dir_items(&dir, walk) {
+            Ok(items) => {
+                for item in items {
+                    match item {
+                        DirItem::Dir(path) => dirs.push_back(path),
+                        DirItem::File(path) => visitor.visit(path),
+                    }
+                }
+            }
+            Err(e) => walk.skip_unreadable_dir(dir, e),
+        }
+    }
+    Ok(())
+}
+
+// Directories waiting to be read by the parallel walk, and how many threads
+// are busy reading one (and so may add more).
+struct WalkQueue {
+    dirs: Vec<PathBuf>,
+    busy: usize,
+}
+
+// Walks the directory tree using several threads, and returns every file
+// found. The files are sorted, which puts them in the same order visit_dirs
+// would visit them, since paths are ordered component by component.
+fn walk_dirs_parallel(dir: &Path, threads: usize, walk: &WalkState) -> io::Result<Vec<PathBuf>> {
+    let found = Mutex::new(Vec::new());
+    walk_dirs_shared(dir, threads, &found, walk)?;
+    let mut files = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    files.sort();
+    Ok(files)
+}
+
+// Walks the directory tree using several threads, each visiting the files it
+// finds as it finds them.
+fn walk_dirs_shared(
+    dir: &Path,
+    threads: usize,
+    visitor: &dyn SharedFileVisitor,
+    walk: &WalkState,
+) -> io::Result<()> {
+    let mut dirs = Vec::new();
+    for item in read_dir_items(dir, walk)? {
+        match item {
+            DirItem::Dir(path) => dirs.push(path),
+            DirItem::File(path) => visitor.visit(path),
+        }
+    }
+
+    let queue = Mutex::new(WalkQueue { dirs, busy: 0 });
+    let ready = Condvar::new();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| walk_dirs_worker(&queue, &ready, visitor, walk));
+        }
+    });
+    Ok(())
+}
+
+// Takes directories off the queue until there are none left and no other
+// thread is still reading one.
+fn walk_dirs_worker(
+    queue: &Mutex<WalkQueue>,
+    ready: &Condvar,
+    visitor: &dyn SharedFileVisitor,
+    walk: &WalkState,
+) {
+    loop {
+        let dir = {
+            let mut state = queue.lock().unwrap();
+            loop {
+                if walk.stopped() {
+                    ready.notify_all();
+                    return;
+                }
+                if let Some(dir) = state.dirs.pop() {
+                    state.busy += 1;
+                    break dir;
+                }
+                if state.busy == 0 {
+                    return;
+                }
+                state = ready.wait(state).unwrap();
+            }
+        };
+
+        let mut subdirs = Vec::new();
+        match read_dir_items(&dir, walk) {
+            Ok(items) => {
+                for item in items {
+                    match item {
+                        DirItem::Dir(path) => subdirs.push(path),
+                        DirItem::File(path) => visitor.visit(path),
+                    }
+                }
+            }
+            Err(e) => walk.skip_unreadable_dir(dir, e),
+        }
+
+        let mut state = queue.lock().unwrap();
+        state.dirs.append(&mut subdirs);
+        state.busy -= 1;
+        ready.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsdup::action::{act_interactively, act_on_duplicates, write_script, Shell};
+    use crate::lsdup::base64;
+    #[cfg(feature = "sqlite")]
+    use crate::lsdup::catalog::write_catalog;
+    use crate::lsdup::checksums;
+    use crate::lsdup::config::{Action, Format, Keep, PathEncoding};
+    use crate::lsdup::hashing::HashAlgorithm;
+    use crate::lsdup::output::{self, hardlink_savings, wasted_by_extension, Formatter};
+    use crate::lsdup::prior;
+    use std::fs::File;
+    use std::io::Write;
+
+    // Records the paths visited, in order.
+    struct VisitedPaths(Vec<PathBuf>);
+
+    impl FileVisitor for VisitedPaths {
+        fn visit(&mut self, file: PathBuf) {
+            self.0.push(file);
+        }
+    }
+
+    fn create_dir_all(target_dir: &Path) {
+        // Start from an empty directory, so files left over from a previous run don't interfere.
+        let _ = std::fs::remove_dir_all(target_dir);
+        std::fs::create_dir_all(target_dir).unwrap_or_else(|error| {
+            if error.kind() != io::ErrorKind::AlreadyExists {
+                panic!("Problem creating directory: {:?}", error);
+            }
+        });
+    }
+
+    #[test]
+    fn test_run() {
+        // Given a directory with two files,
+        let target_dir = Path::new("./target/test_dir/two_files");
+        create_dir_all(target_dir);
+
+        // and both files are identical,
+        let orig_path = target_dir.join(Path::new("a.txt"));
+        {
+            let mut original = File::create(&orig_path).unwrap();
+            original
+                .write_all(b"Contents for a test of two files of identical content. This should create a \"duplicate group\" with one file being marked as original, and the other as a duplicate.")
+                .expect("Could not write data for file.");
+        }
+        let dupe_path = target_dir.join(Path::new("b.txt"));
+        let _ = std::fs::copy(orig_path.as_path(), dupe_path.as_path())
+            .expect("Could not write data for file.");
+
+        // and the configuration is to analyze that directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then one group with two files should be listed,
+        // and "a.txt" is the original and "b.txt" is the duplicate because
+        // alphabetically a.txt comes first.
+        assert_eq!(1, dupes.into_iter().count());
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(2, group.1.len());
+        assert_eq!(orig_path.as_path(), group.1[0].as_path());
+        assert_eq!(dupe_path.as_path(), group.1[1].as_path());
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[test]
+    fn test_print_results_min_waste() {
+        // Given a directory with three copies of a file of 30 bytes, and two
+        // of a file of 50 bytes,
+        let target_dir = Path::new("./target/test_dir/min_waste");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for (names, len) in &[(&["a", "b", "c"][..], 30), (&["d", "e"][..], 50)] {
+            for name in *names {
+                let mut file = File::create(target_dir.join(name)).unwrap();
+                file.write_all(&vec![b'x'; *len])
+                    .expect("Could not write data for file.");
+            }
+        }
+
+        // and the configuration is to only list groups wasting at least 60 bytes,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Ndjson,
+            min_waste: Some(60),
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then only the group of three is listed, since the pair only wastes
+        // 50 bytes, but the summary still counts both.
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(2, lines.len(), "{}", out);
+        assert!(lines[0].starts_with("{\"size\":30,"), "{}", lines[0]);
+        assert!(
+            lines[1].contains("\"duplicates\":3,\"wasted_bytes\":110,\"groups\":2"),
+            "{}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn test_print_results_size_histogram() {
+        // Given a directory with two files of 30 bytes, one of 40, and one of 100,
+        let target_dir = Path::new("./target/test_dir/size_histogram");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for (name, len) in &[("a", 30), ("b", 30), ("c", 40), ("d", 100)] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(&vec![b'y'; *len])
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only count files by size,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            size_histogram: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then nothing is hashed, and the files are counted in the ranges of
+        // 16 to 32 bytes, 32 to 64 bytes, and 64 to 128 bytes, with only the
+        // two of 30 bytes sharing a size.
+        assert_eq!(0, dupes.num_hashed());
+        assert_eq!(
+            vec![(30, 2), (40, 1), (100, 1)],
+            dupes
+                .size_counts()
+                .iter()
+                .map(|(&s, &c)| (s, c))
+                .collect::<Vec<_>>()
+        );
+        let out = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = out
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            vec![
+                vec!["16", "B", "-", "32", "B", "2", "2"],
+                vec!["32", "B", "-", "64", "B", "1", "0"],
+                vec!["64", "B", "-", "128", "B", "1", "0"],
+            ],
+            rows,
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn test_run_adaptive_io() {
+        // Given a directory with ten pairs of identical files,
+        let target_dir = Path::new("./target/test_dir/adaptive_io");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for i in 0..10 {
+            for copy in &["a", "b"] {
+                let mut file = File::create(target_dir.join(format!("{}{}", i, copy))).unwrap();
+                file.write_all(&vec![b'0' + i; 100 + i as usize])
+                    .expect("Could not write data for file.");
+            }
+        }
+
+        // and the configuration is to choose how to read files as they are read,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            adaptive_io: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then every pair is found, whichever way each file was read,
+        assert_eq!(10, dupes.into_iter().count());
+        assert!(dupes.into_iter().all(|(_, paths)| paths.len() == 2));
+
+        // and with enough files read both ways, one of them was chosen.
+        let adaptive_io = dupes.adaptive_io().expect("Expected adaptive I/O.");
+        assert!(adaptive_io.chosen().is_some());
+    }
+
+    #[test]
+    fn test_merge() {
+        // Given two directories, each with a file only the other has a copy
+        // of, and the first with a pair of copies of its own,
+        let target_dir = Path::new("./target/test_dir/merge");
+        let _ = fs::remove_dir_all(target_dir);
+        let (first_dir, second_dir) = (target_dir.join("first"), target_dir.join("second"));
+        create_dir_all(&first_dir);
+        create_dir_all(&second_dir);
+
+        for (path, data) in &[
+            (
+                first_dir.join("a.txt"),
+                &b"Contents split across scans. wsxedc"[..],
+            ),
+            (
+                first_dir.join("c1.txt"),
+                &b"Contents of a pair within a scan. rfvtgbyhn"[..],
+            ),
+            (
+                first_dir.join("c2.txt"),
+                &b"Contents of a pair within a scan. rfvtgbyhn"[..],
+            ),
+            (
+                second_dir.join("b.txt"),
+                &b"Contents split across scans. wsxedc"[..],
+            ),
+            (
+                second_dir.join("d.txt"),
+                &b"Contents only the second has."[..],
+            ),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and each directory was scanned on its own,
+        let first_config = Config {
+            dirs: vec![first_dir.clone()],
+            ..Default::default()
+        };
+        let second_config = Config {
+            dirs: vec![second_dir.clone()],
+            ..Default::default()
+        };
+        let mut merged = run(&first_config).expect("Could not analyze directory.");
+        let second = run(&second_config).expect("Could not analyze directory.");
+        assert_eq!(1, merged.into_iter().count());
+
+        // When the scans are merged,
+        merged.merge(second);
+
+        // Then the files of both are counted, and the file with a copy in the
+        // other scan is in a group with it now too.
+        assert_eq!(5, merged.num_files());
+        let groups: Vec<_> = merged.into_iter().map(|(_, paths)| paths.clone()).collect();
+        assert_eq!(
+            vec![
+                vec![first_dir.join("c1.txt"), first_dir.join("c2.txt")],
+                vec![first_dir.join("a.txt"), second_dir.join("b.txt")],
+            ],
+            groups
+        );
+
+        // And when a scan of files already counted is merged too, they aren't
+        // counted again.
+        let again = run(&first_config).expect("Could not analyze directory.");
+        merged.merge(again);
+        assert_eq!(5, merged.num_files());
+        assert_eq!(4, merged.num_hashed());
+        assert_eq!(
+            vec![(29, 1), (35, 2), (43, 2)],
+            merged
+                .size_counts()
+                .iter()
+                .map(|(&s, &c)| (s, c))
+                .collect::<Vec<_>>()
+        );
+        let regrouped: Vec<_> = merged.into_iter().map(|(_, paths)| paths.clone()).collect();
+        assert_eq!(groups, regrouped);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_case_fold() {
+        // Given a directory with two different files, and a symlink to it
+        // spelled with other case, as a case insensitive filesystem has,
+        let target_dir = Path::new("./target/test_dir/case_fold");
+        let _ = fs::remove_dir_all(target_dir);
+        let photos_dir = target_dir.join("Photos");
+        create_dir_all(&photos_dir);
+        std::os::unix::fs::symlink("Photos", target_dir.join("photos")).unwrap();
+
+        for (name, data) in &[
+            ("a.jpg", &b"Contents of a photo. qazwsx"[..]),
+            ("b.jpg", b"Another."),
+        ] {
+            let mut file = File::create(photos_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and both spellings are given to be scanned,
+        let mut config = Config {
+            dirs: vec![photos_dir.clone(), target_dir.join("photos")],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed without folding case,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then each file is found twice, and looks like its own duplicate,
+        assert_eq!(4, dupes.num_files());
+        assert_eq!(2, dupes.into_iter().count());
+
+        // but when folding case, each file is only found once.
+        config.case_fold = true;
+        let dupes = run(&config).expect("Could not analyze directory.");
+        assert_eq!(2, dupes.num_files());
+        assert_eq!(0, dupes.into_iter().count());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_case_fold_not_unicode() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        // Given two directories whose names aren't valid Unicode, and only
+        // differ in the bytes that aren't, each with a copy of a file,
+        let target_dir = Path::new("./target/test_dir/case_fold_not_unicode");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+        for name in &[&b"dir\xfe"[..], b"dir\xff"] {
+            let dir = target_dir.join(OsStr::from_bytes(name));
+            std::fs::create_dir(&dir).unwrap();
+            let mut file = File::create(dir.join("a.txt")).unwrap();
+            file.write_all(b"Contents for a test of names that aren't Unicode. ujmik")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to fold case,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            case_fold: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then both directories are scanned, and their files are duplicates.
+        assert_eq!(2, dupes.num_files());
+        assert_eq!(1, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_any() {
+        // Given a directory with a pair of copies, another file, and another
+        // pair of copies, in that order by name,
+        let target_dir = Path::new("./target/test_dir/any");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a1.txt", &b"Contents of the first pair. plmokn"[..]),
+            ("a2.txt", &b"Contents of the first pair. plmokn"[..]),
+            ("b.txt", &b"Contents of no pair."[..]),
+            (
+                "c1.txt",
+                &b"Contents of the second pair, a bit longer. ijnuhb"[..],
+            ),
+            (
+                "c2.txt",
+                &b"Contents of the second pair, a bit longer. ijnuhb"[..],
+            ),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to stop at any duplicates,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            any: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the scan stopped at the first pair, without visiting the rest,
+        assert!(dupes.found_any());
+        assert_eq!(2, dupes.num_files());
+
+        // and only that pair is listed.
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("a1.txt") && out.contains("a2.txt"), "{}", out);
+        assert!(!out.contains("c1.txt"), "{}", out);
+    }
+
+    #[test]
+    fn test_run_min_size() {
+        // Given a directory with two empty files, and two identical files of 40 bytes,
+        let target_dir = Path::new("./target/test_dir/min_size");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt"] {
+            File::create(target_dir.join(name)).expect("Could not create file.");
+        }
+        for name in &["c.txt", "d.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(&[b'x'; 40])
+                .expect("Could not write data for file.");
+        }
+
+        // When dupes are analyzed with the default minimum size,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the empty files are left out.
+        assert_eq!(2, dupes.num_files());
+        let lens: Vec<u64> = dupes
+            .into_iter()
+            .map(|(lenhash, _)| lenhash.len())
+            .collect();
+        assert_eq!(vec![40], lens);
+
+        // When dupes are analyzed with a minimum size of 0,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            min_size: 0,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the empty files are a group too.
+        assert_eq!(4, dupes.num_files());
+        let lens: Vec<u64> = dupes
+            .into_iter()
+            .map(|(lenhash, _)| lenhash.len())
+            .collect();
+        assert_eq!(vec![40, 0], lens);
+
+        // When dupes are analyzed with a minimum size larger than every file,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            min_size: 41,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then no files are compared.
+        assert_eq!(0, dupes.num_files());
+    }
+
+    #[test]
+    fn test_run_no_hash() {
+        // Given a directory with two identical files and another file,
+        let target_dir = Path::new("./target/test_dir/no_hash");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            (
+                "a.txt",
+                &b"Contents for a test of not hashing. okmijnuhb"[..],
+            ),
+            (
+                "b.txt",
+                &b"Contents for a test of not hashing. okmijnuhb"[..],
+            ),
+            ("c.txt", &b"Other contents."[..]),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only count the files,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            no_hash: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then every file and byte is counted, but none are hashed or grouped.
+        assert_eq!(3, dupes.num_files());
+        assert_eq!(105, dupes.total_file_bytes());
+        assert_eq!(0, dupes.num_hashed());
+        assert_eq!(0, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_min_count() {
+        // Given a directory with five files,
+        let target_dir = Path::new("./target/test_dir/min_count");
+        create_dir_all(target_dir);
+
+        // and three of the files are identical,
+        let triple_data = b"Contents for a test of three files of identical content. qpwoeiru";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(triple_data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the other two are identical to each other,
+        let double_data = b"Contents for a test of two files of identical content. zmxncbv";
+        for name in &["d.txt", "e.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(double_data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only list groups of at least three files,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            min_count: 3,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the group of three files should be listed, which is the largest.
+        let largest = dupes.largest_group().expect("Expected a group.");
+        assert_eq!(3, largest.1.len());
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(3, group.1.len());
+        assert_eq!(target_dir.join("a.txt"), group.1[0]);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[test]
+    fn test_run_modified_after() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/modified_after");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of modification time filtering. alskdjfh";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the first file was last modified thirty days ago,
+        let now = std::time::SystemTime::now();
+        let thirty_days = std::time::Duration::from_secs(30 * 24 * 60 * 60);
+        File::options()
+            .write(true)
+            .open(target_dir.join("a.txt"))
+            .and_then(|file| file.set_modified(now - thirty_days))
+            .expect("Could not set modification time.");
+
+        // and the configuration is to only compare files modified in the last week,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            modified_after: Some(
+                lsdup::mtime::parse("7d", now).expect("Could not parse relative time."),
+            ),
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the two recently modified files should be grouped.
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(2, group.1.len());
+        assert_eq!(target_dir.join("b.txt"), group.1[0]);
+        assert_eq!(target_dir.join("c.txt"), group.1[1]);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[test]
+    fn test_run_extensions() {
+        // Given a directory with identical files of different extensions,
+        let target_dir = Path::new("./target/test_dir/extensions");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of extension filtering. pqowieur";
+        for name in &["a.JPG", "b.jpg", "c.png", "d", "e"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only compare jpg files,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            extensions: Some(vec!["jpg".to_string()]),
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the jpg files should be grouped, regardless of case,
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(2, group.1.len());
+        assert_eq!(target_dir.join("a.JPG"), group.1[0]);
+        assert_eq!(target_dir.join("b.jpg"), group.1[1]);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+
+        // and when files without an extension are explicitly included,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            extensions: Some(vec!["png".to_string(), "".to_string()]),
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the png file is grouped with the files without an extension.
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(3, group.1.len());
+        assert_eq!(target_dir.join("c.png"), group.1[0]);
+    }
+
+    #[test]
+    fn test_visit_file_changed_size() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/changed_size");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of a file changing while scanned. mznxbcv";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the first file is truncated after it is visited but before it is hashed,
+        let config = Config::default();
+        let mut dupes = AllInFileVisitor::new(&config);
+        dupes.visit(target_dir.join("a.txt"));
+        File::create(target_dir.join("a.txt")).expect("Could not truncate file.");
+
+        // When the other files are visited,
+        dupes.visit(target_dir.join("b.txt"));
+        dupes.visit(target_dir.join("c.txt"));
+
+        // Then the changed file is left out, and only the unchanged files are grouped.
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(2, group.1.len());
+        assert_eq!(target_dir.join("b.txt"), group.1[0]);
+        assert_eq!(target_dir.join("c.txt"), group.1[1]);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+
+        // and the changed file is reported as one that could not be read.
+        assert_eq!(1, dupes.unreadable().len());
+        assert_eq!(target_dir.join("a.txt"), dupes.unreadable()[0].0);
+    }
+
+    #[test]
+    fn test_visit_file_vanished() {
+        // Given a file that was listed in its directory but removed since,
+        let target_dir = Path::new("./target/test_dir/vanished");
+        create_dir_all(target_dir);
+        let path = target_dir.join("a.txt");
+        File::create(&path).expect("Could not create file.");
+        fs::remove_file(&path).expect("Could not remove file.");
+
+        // When the file is visited,
+        let config = Config::default();
+        let mut dupes = AllInFileVisitor::new(&config);
+        dupes.visit(path);
+
+        // Then it is skipped, and not reported as one that could not be read.
+        assert_eq!(0, dupes.num_files());
+        assert!(dupes.unreadable().is_empty());
+    }
+
+    #[test]
+    fn test_run_mmap_threshold() {
+        // Given a directory with two identical files, big enough to be worth mapping,
+        let target_dir = Path::new("./target/test_dir/mmap_threshold");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of mapped and read files hashing the same. ".repeat(1000);
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(&data)
+                .expect("Could not write data for file.");
+        }
+
+        // When dupes are analyzed once always memory mapping the files,
+        let mapped_config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            mmap_threshold: 0,
+            ..Default::default()
+        };
+        let mapped = run(&mapped_config).expect("Could not analyze directory.");
+
+        // and once never memory mapping the files,
+        let read_config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            mmap_threshold: u64::MAX,
+            ..Default::default()
+        };
+        let read = run(&read_config).expect("Could not analyze directory.");
+
+        // Then both ways find the same group, with the same size and hash.
+        let mapped_group = mapped.into_iter().next().unwrap();
+        let read_group = read.into_iter().next().unwrap();
+        assert_eq!(data.len() as u64, mapped_group.0.len());
+        assert_eq!(mapped_group.0, read_group.0);
+        assert_eq!(mapped_group.1, read_group.1);
+    }
+
+    #[test]
+    fn test_run_threads() {
+        // Given a tree of directories with identical files spread throughout,
+        let target_dir = Path::new("./target/test_dir/threads");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of walking directories in parallel. woeiruty";
+        for dir in &["x", "x/y", "x/y/z", "w", "w/v"] {
+            create_dir_all(&target_dir.join(dir));
+        }
+        for name in &[
+            "a.txt",
+            "x/b.txt",
+            "x/y/c.txt",
+            "x/y/z/d.txt",
+            "w/v/e.txt",
+            "w/f.txt",
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // When dupes are analyzed for that directory with one thread,
+        let serial_config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let serial = run(&serial_config).expect("Could not analyze directory.");
+
+        // and with several threads,
+        let parallel_config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            threads: 4,
+            ..Default::default()
+        };
+        let parallel = run(&parallel_config).expect("Could not analyze directory.");
+
+        // Then both find the same group, with the files in the same order.
+        let serial_group = serial.into_iter().next().unwrap();
+        let parallel_group = parallel.into_iter().next().unwrap();
+        assert_eq!(6, serial_group.1.len());
+        assert_eq!(serial_group.1, parallel_group.1);
+    }
+
+    #[test]
+    fn test_run_keep_newest() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/keep_newest");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of keeping the newest file. xmcnvbeiru";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the second file is the newest, and the first is the oldest,
+        let now = std::time::SystemTime::now();
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        for (name, age) in &[("a.txt", 3), ("b.txt", 1), ("c.txt", 2)] {
+            File::options()
+                .write(true)
+                .open(target_dir.join(name))
+                .and_then(|file| file.set_modified(now - day * *age))
+                .expect("Could not set modification time.");
+        }
+
+        // and the configuration is to keep the newest file,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            keep: Keep::Newest,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the newest file is listed first as the original, followed by the others from
+        // newest to oldest.
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(target_dir.join("b.txt"), group.1[0]);
+        assert_eq!(target_dir.join("c.txt"), group.1[1]);
+        assert_eq!(target_dir.join("a.txt"), group.1[2]);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_print_results_show_times_archive() {
+        // Given a directory with a file, and a zip archive with a copy of it
+        // modified at a known time,
+        let target_dir = Path::new("./target/test_dir/show_times_archive");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of the times of archive entries. plokmijn";
+        let mut original = File::create(target_dir.join("a.txt")).unwrap();
+        original
+            .write_all(data)
+            .expect("Could not write data for file.");
+        {
+            let mut zip = zip::ZipWriter::new(File::create(target_dir.join("b.zip")).unwrap());
+            let options = zip::write::FileOptions::default().last_modified_time(
+                zip::DateTime::from_date_and_time(2023, 1, 15, 10, 30, 6).unwrap(),
+            );
+            zip.start_file("a.txt", options).unwrap();
+            zip.write_all(data)
+                .expect("Could not write data for entry.");
+            zip.finish().expect("Could not finish archive.");
+        }
+
+        // and the configuration is to scan within archives, showing times,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            scan_archives: true,
+            show_times: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the entry has the time recorded in the archive, and no changed
+        // time, which archives don't keep.
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("b.zip!a.txt  (modified 2023-01-15T10:30:06Z, changed -)"),
+            "{}",
+            out
+        );
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_run_scan_archives() {
+        // Given a directory with a file,
+        let target_dir = Path::new("./target/test_dir/scan_archives");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of files within archives. bvcnxmzlaksj";
+        let orig_path = target_dir.join("a.txt");
+        {
+            let mut original = File::create(&orig_path).unwrap();
+            original
+                .write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and a zip archive containing a copy of that file, and a different file,
+        let archive_path = target_dir.join("b.zip");
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+            let options = zip::write::FileOptions::default();
+            zip.start_file("copy/a.txt", options).unwrap();
+            zip.write_all(data)
+                .expect("Could not write data for entry.");
+            zip.start_file("other.txt", options).unwrap();
+            zip.write_all(b"Different contents.")
+                .expect("Could not write data for entry.");
+            zip.finish().expect("Could not finish archive.");
+        }
+
+        // and the configuration is to scan within archives,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            scan_archives: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the file and its copy within the archive are grouped together.
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(2, group.1.len());
+        assert_eq!(orig_path, group.1[0]);
+        assert_eq!(target_dir.join("b.zip!copy/a.txt"), group.1[1]);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_run_scan_archives_filtered() {
+        // Given a directory with a file,
+        let target_dir = Path::new("./target/test_dir/scan_archives_filtered");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of filtering archive entries. edcrfvtgb";
+        let orig_path = target_dir.join("a.txt");
+        let mut original = File::create(&orig_path).unwrap();
+        original
+            .write_all(data)
+            .expect("Could not write data for file.");
+
+        // and a zip archive with copies of it that are excluded, hidden, or
+        // modified long ago, and one that is none of those,
+        let archive_path = target_dir.join("b.zip");
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+            let at = |year| {
+                zip::write::FileOptions::default().last_modified_time(
+                    zip::DateTime::from_date_and_time(year, 1, 1, 0, 0, 0).unwrap(),
+                )
+            };
+            for (name, year) in &[
+                ("b.tmp", 2020),
+                ("build/c.txt", 2020),
+                (".hidden/d.txt", 2020),
+                ("old/e.txt", 1990),
+                ("f.txt", 2020),
+            ] {
+                zip.start_file(*name, at(*year)).unwrap();
+                zip.write_all(data)
+                    .expect("Could not write data for entry.");
+            }
+            zip.finish().expect("Could not finish archive.");
+        }
+
+        // and the configuration leaves those out,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            scan_archives: true,
+            excludes: vec![
+                glob::Pattern::new("*.tmp").unwrap(),
+                glob::Pattern::new("build").unwrap(),
+            ],
+            skip_hidden: true,
+            modified_after: Some(
+                lsdup::mtime::parse("2000-01-01", std::time::SystemTime::now()).unwrap(),
+            ),
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the entries are left out the same as files would be.
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(&vec![orig_path, target_dir.join("b.zip!f.txt")], group.1);
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[cfg(feature = "tar")]
+    #[test]
+    fn test_run_scan_tar_archives() {
+        // Given a directory with a file,
+        let target_dir = Path::new("./target/test_dir/scan_tar_archives");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of files within tar archives. qazxswedc";
+        let orig_path = target_dir.join("a.txt");
+        fs::write(&orig_path, data).expect("Could not write data for file.");
+
+        // and a tar and a gzipped tar, each with a copy of that file, a
+        // directory, and a symlink,
+        for (name, gzipped) in &[("b.tar", false), ("c.tar.gz", true)] {
+            let file = File::create(target_dir.join(name)).unwrap();
+            let out: Box<dyn Write> = if *gzipped {
+                Box::new(flate2::write::GzEncoder::new(
+                    file,
+                    flate2::Compression::default(),
+                ))
+            } else {
+                Box::new(file)
+            };
+            let mut tar = tar::Builder::new(out);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, "copy/a.txt", &data[..])
+                .expect("Could not write data for entry.");
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            header.set_mode(0o755);
+            tar.append_data(&mut header, "dir/", io::empty())
+                .expect("Could not write directory entry.");
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            header.set_mode(0o777);
+            tar.append_link(&mut header, "link.txt", "copy/a.txt")
+                .expect("Could not write symlink entry.");
+            tar.into_inner()
+                .and_then(|mut out| out.flush())
+                .expect("Could not finish archive.");
+        }
+
+        // and the configuration is to scan within archives,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            scan_archives: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the file and its copies within the archives are grouped
+        // together, and the directories and symlinks within them are skipped.
+        let mut iter = dupes.into_iter();
+        let group = iter.next().unwrap();
+        assert_eq!(
+            vec![
+                orig_path,
+                target_dir.join("b.tar!copy/a.txt"),
+                target_dir.join("c.tar.gz!copy/a.txt")
+            ],
+            *group.1
+        );
+        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+    }
+
+    #[test]
+    fn test_reflink_dry_run() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/reflink_dry_run");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of a reflink dry run. qazwsxedc";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only report what reflinking would do,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            action: Some(Action::Reflink),
+            dry_run: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed and acted on,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let report = act_on_duplicates(&dupes, &config);
+
+        // Then the two duplicates would be reflinked to the original,
+        assert_eq!(2, report.files);
+        assert_eq!(2 * data.len() as u64, report.bytes);
+        assert_eq!(0, report.failed);
+
+        // and no files were changed.
+        let mut names: Vec<_> = fs::read_dir(target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(vec!["a.txt", "b.txt", "c.txt"], names);
+    }
+
+    #[test]
+    fn test_walk_shared() {
+        // Given a tree of directories with a file in each, and a file given directly,
+        let target_dir = Path::new("./target/test_dir/walk_shared");
+        let _ = fs::remove_dir_all(target_dir);
+        let mut expected = Vec::new();
+        for sub in &["a", "a/b", "c", "c/d/e"] {
+            let dir = target_dir.join(sub);
+            create_dir_all(&dir);
+            let file = dir.join("f.txt");
+            File::create(&file).expect("Could not create file.");
+            expected.push(file);
+        }
+        let given = target_dir.join("given.txt");
+        File::create(&given).expect("Could not create file.");
+        expected.push(given.clone());
+        expected.sort();
+
+        for threads in &[1, 4] {
+            // When the tree is walked by several threads with a shared visitor,
+            let config = Config {
+                dirs: vec![target_dir.join("a"), target_dir.join("c"), given.clone()],
+                threads: *threads,
+                ..Default::default()
+            };
+            let found = Mutex::new(Vec::new());
+            walk_shared(&config, &found).expect("Could not walk directories.");
+
+            // Then every file is visited once.
+            let mut found = found.into_inner().unwrap();
+            found.sort();
+            assert_eq!(expected, found);
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_write_script() {
+        // Given a directory with three identical files, one with a quote in its name,
+        let target_dir = Path::new("./target/test_dir/write_script");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of a cleanup script. wsxedcrfv";
+        for name in &["a.txt", "b.txt", "it's c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // When a script is written for the duplicates,
+        let mut script = Vec::new();
+        let report =
+            write_script(&dupes, &config, Shell::Sh, &mut script).expect("Could not write script.");
+
+        // Then it removes the two duplicates, keeping the first file,
+        assert_eq!(2, report.files);
+        assert_eq!(2 * data.len() as u64, report.bytes);
+        let script = String::from_utf8(script).unwrap();
+        let dir = std::path::absolute(target_dir).unwrap();
+        assert!(script.starts_with("#!/bin/sh\n"), "{}", script);
+        assert!(
+            script.contains(&format!(
+                "# 3 identical files of 50 B, keeping {:?}\nrm -- '{}'\nrm -- '{}'\n",
+                dir.join("a.txt"),
+                dir.join("b.txt").display(),
+                dir.join("it'\\''s c.txt").display()
+            )),
+            "{}",
+            script
+        );
+
+        // and no files are changed until it is run,
+        assert_eq!(3, fs::read_dir(target_dir).unwrap().count());
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&script)
+            .status()
+            .expect("Could not run script.");
+        assert!(status.success());
+
+        // and then only the original remains.
+        let names: Vec<_> = fs::read_dir(target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(vec!["a.txt"], names);
+    }
+
+    #[cfg(all(target_family = "unix", feature = "zip"))]
+    #[test]
+    fn test_write_script_archive_entries() {
+        // Given a directory with two identical files, and a zip archive with
+        // two more copies of them,
+        let target_dir = Path::new("./target/test_dir/write_script_archive_entries");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of scripts with archives. plokmijnu";
+        for name in &["b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        {
+            let mut zip = zip::ZipWriter::new(File::create(target_dir.join("a.zip")).unwrap());
+            let options = zip::write::FileOptions::default();
+            for name in &["a.txt", "d.txt"] {
+                zip.start_file(*name, options).unwrap();
+                zip.write_all(data)
+                    .expect("Could not write data for entry.");
+            }
+            zip.finish().expect("Could not finish archive.");
+        }
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            scan_archives: true,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        assert_eq!(4, dupes.into_iter().next().unwrap().1.len());
+
+        // When a script is written for the duplicates,
+        let mut script = Vec::new();
+        let report =
+            write_script(&dupes, &config, Shell::Sh, &mut script).expect("Could not write script.");
+
+        // Then the entries within the archive are left out, keeping the first
+        // file that isn't one.
+        assert_eq!(1, report.files);
+        let script = String::from_utf8(script).unwrap();
+        let dir = std::path::absolute(target_dir).unwrap();
+        assert!(
+            script.contains(&format!(
+                "# 2 identical files of 55 B, keeping {:?}\nrm -- '{}'\n",
+                dir.join("b.txt"),
+                dir.join("c.txt").display()
+            )),
+            "{}",
+            script
+        );
+        assert!(!script.contains("a.zip!"), "{}", script);
+    }
+
+    #[test]
+    fn test_interactive_delete() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/interactive_delete");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of interactive deletion. plokmijnu";
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            action: Some(Action::Delete),
+            interactive: true,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // When quitting at the first group,
+        let mut out = Vec::new();
+        let report = act_interactively(&dupes, &config, &mut &b"q\n"[..], &mut out)
+            .expect("Could not ask which file to keep.");
+
+        // Then nothing is deleted.
+        assert_eq!(0, report.files);
+        assert_eq!(3, fs::read_dir(target_dir).unwrap().count());
+
+        // When a bad answer is given, and then the second file is kept,
+        let mut out = Vec::new();
+        let report = act_interactively(&dupes, &config, &mut &b"x\n2\n"[..], &mut out)
+            .expect("Could not ask which file to keep.");
+
+        // Then the question is asked again,
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("Please answer with a number from 1 to 3"),
+            "{}",
+            out
+        );
+
+        // and only the second file remains.
+        assert_eq!(2, report.files);
+        assert_eq!(2 * data.len() as u64, report.bytes);
+        assert_eq!(0, report.failed);
+        let names: Vec<_> = fs::read_dir(target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(vec!["b.txt"], names);
+    }
+
+    #[test]
+    fn test_run_hashed_counts() {
+        // Given a directory with two identical files, and a file of a different size,
+        let target_dir = Path::new("./target/test_dir/hashed_counts");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of counting hashed files. rtyfghvbn";
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        {
+            let mut file = File::create(target_dir.join("c.txt")).unwrap();
+            file.write_all(b"Unique size.")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to analyze that directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the two same-size files were hashed, and the other was not.
+        assert_eq!(2, dupes.num_hashed());
+        assert_eq!(1, dupes.num_unique_sizes());
+    }
+
+    #[test]
+    fn test_root_totals() {
+        // Given two directories, the first with two copies of a file and
+        // another file, and the second with one more copy,
+        let target_dir = Path::new("./target/test_dir/root_totals");
+        let _ = fs::remove_dir_all(target_dir);
+        let first = target_dir.join("first");
+        let second = target_dir.join("second");
+        create_dir_all(&first);
+        create_dir_all(&second);
+
+        let data = b"Contents for a test of totals by directory. ikmolpujn";
+        for path in &[
+            first.join("a.txt"),
+            first.join("b.txt"),
+            second.join("c.txt"),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        {
+            let mut file = File::create(first.join("d.txt")).unwrap();
+            file.write_all(b"Unique.")
+                .expect("Could not write data for file.");
+        }
+
+        let config = Config {
+            dirs: vec![first, second],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for both directories,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then each directory's files and duplicates are counted under it.
+        let totals = dupes.root_totals();
+        assert_eq!(2, totals.len());
+        assert_eq!(3, totals[0].files);
+        assert_eq!(2 * data.len() as u64 + 7, totals[0].bytes);
+        assert_eq!(1, totals[0].duplicates);
+        assert_eq!(1, totals[1].files);
+        assert_eq!(1, totals[1].duplicates);
+    }
+
+    #[test]
+    fn test_all_groups() {
+        // Given a directory with two files of the same size but different contents,
+        let target_dir = Path::new("./target/test_dir/all_groups");
+        create_dir_all(target_dir);
+
+        for (name, contents) in &[
+            ("a.txt", b"Same size, not alike. 1"),
+            ("b.txt", b"Same size, not alike. 2"),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(*contents)
+                .expect("Could not write data for file.");
+        }
+
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then there are no groups of duplicates,
+        assert_eq!(0, dupes.into_iter().count());
+
+        // but both hashed files are among all the groups, each on its own.
+        let all: Vec<_> = dupes.all_groups().map(|(_, paths)| paths.len()).collect();
+        assert_eq!(vec![1, 1], all);
+    }
+
+    #[test]
+    fn test_run_ends_check() {
+        // Given a directory with two identical large files, and a third of the
+        // same size that only differs in the middle, and a fourth that differs
+        // at the end,
+        let target_dir = Path::new("./target/test_dir/ends_check");
+        create_dir_all(target_dir);
+
+        let data = vec![b'e'; 3 * 64 * 1024];
+        let mut middle = data.clone();
+        middle[data.len() / 2] = b'm';
+        let mut end = data.clone();
+        *end.last_mut().unwrap() = b'z';
+        for (name, contents) in &[
+            ("a.bin", &data),
+            ("b.bin", &data),
+            ("c.bin", &middle),
+            ("d.bin", &end),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(contents)
+                .expect("Could not write data for file.");
+        }
+
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the identical files are a group,
+        let groups: Vec<_> = dupes.into_iter().collect();
+        assert_eq!(1, groups.len());
+        assert_eq!(
+            &vec![target_dir.join("a.bin"), target_dir.join("b.bin")],
+            groups[0].1
+        );
+
+        // and the file that differs at the end was never hashed whole, while
+        // the one with the same ends was.
+        assert_eq!(3, dupes.num_hashed());
+        assert_eq!(1, dupes.num_unique_ends());
+
+        // When the ends aren't checked first,
+        let config = Config {
+            ends_check: false,
+            ..config
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then every file of the same size is hashed whole, with the same groups.
+        assert_eq!(4, dupes.num_hashed());
+        assert_eq!(1, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_ignore_case() {
+        // Given a directory with two identical files, differing in case,
+        let target_dir = Path::new("./target/test_dir/ignore_case");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of ignoring case. plmoknijb";
+        for name in &["a.txt", "B.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to ignore case when ordering,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ignore_case: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then "a.txt" is the original, even though "B.txt" sorts first when case matters.
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(target_dir.join("a.txt"), group.1[0]);
+        assert_eq!(target_dir.join("B.txt"), group.1[1]);
+    }
+
+    #[test]
+    fn test_hash_contents_path() {
+        // Given a directory with two identical files,
+        let target_dir = Path::new("./target/test_dir/hash_contents_path");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of the public hashing function. ujmyhntgb";
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // When dupes are analyzed for that directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then hashing one of the files directly gives the same length and hash as the group.
+        let group = dupes.into_iter().next().unwrap();
+        let lenhash = hash_contents_path(&target_dir.join("a.txt")).expect("Could not hash file.");
+        assert_eq!(group.0, &lenhash);
+        assert_eq!(data.len() as u64, lenhash.len());
+    }
+
+    #[test]
+    fn test_run_quick() {
+        // Given a directory with two files of the same size, which only differ at the end,
+        let target_dir = Path::new("./target/test_dir/quick");
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a.txt", b"Same start for a test of quick mode. 1"),
+            ("b.txt", b"Same start for a test of quick mode. 2"),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(*data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only compare the first bytes of files,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            quick: Some(16),
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the two files are grouped, since their size and first bytes match,
+        let group = dupes.into_iter().next().expect("Expected a group.");
+        assert_eq!(38, group.0.len());
+        assert_eq!(2, group.1.len());
+
+        // and the group is labelled as unverified.
+        let mut out = Vec::new();
+        output::Ndjson { config: &config }
+            .group(&mut out, &dupes.group(group.0, group.1))
+            .expect("Could not write group.");
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("\"verified\":false"));
+    }
+
+    #[test]
+    fn test_print_results_to_writer() {
+        // Given a directory with two identical files,
+        let target_dir = Path::new("./target/test_dir/print_results");
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"Contents for a test of the output. wsxedcrfv")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to write the groups in fdupes format,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Fdupes,
+            ..Default::default()
+        };
+
+        // When the results are printed to a writer,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the group is written to it.
+        let expected = format!(
+            "{}\n{}\n\n",
+            target_dir.join("a.txt").display(),
+            target_dir.join("b.txt").display()
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_print_results_same_name() {
+        // Given a directory with a file, a browser-named copy of it, and a
+        // copy of it with another name,
+        let target_dir = Path::new("./target/test_dir/same_name");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for name in &["report.pdf", "report (1).pdf", "summary.pdf"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"Contents for a test of same names. tgbyhnujm")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only list files with the same name,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Fdupes,
+            same_name: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then only the report and its copy are listed.
+        let expected = format!(
+            "{}\n{}\n\n",
+            target_dir.join("report (1).pdf").display(),
+            target_dir.join("report.pdf").display()
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_print_results_limit() {
+        // Given a directory with two groups of duplicates, of different sizes,
+        let target_dir = Path::new("./target/test_dir/limit");
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a.txt", &b"Shorter contents. qazwsx"[..]),
+            ("b.txt", &b"Shorter contents. qazwsx"[..]),
+            ("c.txt", &b"Longer contents for the limit test. edcrfv"[..]),
+            ("d.txt", &b"Longer contents for the limit test. edcrfv"[..]),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to only print the first group,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Fdupes,
+            limit: Some(1),
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then only the group of larger files is printed.
+        let expected = format!(
+            "{}\n{}\n\n",
+            target_dir.join("c.txt").display(),
+            target_dir.join("d.txt").display()
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_print_results_ndjson_summary() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/ndjson_summary");
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"0123456789")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to write ndjson,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Ndjson,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the two copies of the first file are counted as duplicates,
+        assert_eq!(2, dupes.num_duplicate_files());
+        assert_eq!(20, dupes.wasted_bytes());
+
+        // and the group is followed by a line with the summary.
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("{\"size\":10,"));
+        assert_eq!(
+            "{\"summary\":{\"files\":3,\"bytes\":30,\"duplicates\":2,\"wasted_bytes\":20,\"groups\":1}}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn test_print_results_quiet() {
+        // Given a directory with two identical files,
+        let target_dir = Path::new("./target/test_dir/print_results_quiet");
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"Contents for a test of --quiet. tgbyhn")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to write ndjson without the summary,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Ndjson,
+            quiet: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the group and the totals of the ndjson are still written, only
+        // the summary on stderr is left out.
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(2, lines.len(), "{}", out);
+        assert!(lines[0].starts_with("{\"size\":38,"), "{}", lines[0]);
+        assert!(
+            lines[1].starts_with("{\"summary\":{\"files\":2,"),
+            "{}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn test_run_skip_hidden() {
+        // Given a directory with a file, a hidden copy of it, and a hidden directory with a copy,
+        let target_dir = Path::new("./target/test_dir/skip_hidden");
+        create_dir_all(target_dir);
+        let hidden_dir = target_dir.join(".hidden");
+        std::fs::create_dir(&hidden_dir).unwrap();
+
+        for path in &[
+            target_dir.join("a.txt"),
+            target_dir.join(".b.txt"),
+            hidden_dir.join("c.txt"),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of hidden files. olpikujyh")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to skip hidden files and directories,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            skip_hidden: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the file that isn't hidden is analyzed, so there are no duplicates.
+        assert_eq!(1, dupes.num_files());
+        assert_eq!(0, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_two_hashes() {
+        // Given a directory with two identical files,
+        let target_dir = Path::new("./target/test_dir/two_hashes");
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"abc")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to hash with SHA-256 and BLAKE3,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            hashes: vec![HashAlgorithm::Sha256, HashAlgorithm::Blake3],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the group has both hashes, the first algorithm's first.
+        let groups: Vec<_> = dupes.into_iter().collect();
+        assert_eq!(1, groups.len());
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            groups[0].0.to_hex().as_str()
+        );
+        assert_eq!(
+            blake3::hash(b"abc").to_hex().as_str(),
+            groups[0].0.extra_hex().unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn test_run_until_stopped() {
+        // Given a directory with two identical files,
+        let target_dir = Path::new("./target/test_dir/run_until_stopped");
+        create_dir_all(target_dir);
+
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"Contents for a test of stopping. wsxcderfv")
+                .expect("Could not write data for file.");
+        }
+
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When the scan is run but has already been told to stop,
+        let stop = AtomicBool::new(true);
+        let dupes = run_until(&config, &stop).expect("Could not analyze directory.");
+
+        // Then no files are visited, and the result says it was interrupted.
+        assert_eq!(0, dupes.num_files());
+        assert!(dupes.interrupted());
+
+        // When it isn't told to stop,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then every file is visited.
+        assert_eq!(2, dupes.num_files());
+        assert!(!dupes.interrupted());
+    }
+
+    #[test]
+    fn test_run_sample() {
+        // Given a directory with twenty pairs of identical files, each pair of
+        // a different size,
+        let target_dir = Path::new("./target/test_dir/sample");
+        create_dir_all(target_dir);
+
+        for i in 0..20 {
+            let data = vec![b's'; i + 1];
+            for copy in &["a", "b"] {
+                let mut file = File::create(target_dir.join(format!("{}{}.txt", copy, i))).unwrap();
+                file.write_all(&data)
+                    .expect("Could not write data for file.");
+            }
+        }
+
+        // and the configuration is to sample half of the sizes,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            sample: Some(50.0),
+            seed: 7,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only some of the files are compared, but both of each pair are,
+        let files = dupes.num_files();
+        assert!(files > 0 && files < 40, "{} files sampled", files);
+        assert_eq!(files as usize, 2 * dupes.into_iter().count());
+
+        // and the same seed picks the same files again.
+        let again = run(&config).expect("Could not analyze directory.");
+        assert_eq!(files, again.num_files());
+    }
+
+    #[test]
+    fn test_run_exclude() {
+        // Given a directory with a file, a copy with another extension, and
+        // a directory with a copy,
+        let target_dir = Path::new("./target/test_dir/exclude");
+        let _ = fs::remove_dir_all(target_dir);
+        let build_dir = target_dir.join("build");
+        create_dir_all(&build_dir);
+
+        for path in &[
+            target_dir.join("a.txt"),
+            target_dir.join("b.tmp"),
+            build_dir.join("c.txt"),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of excludes. rfvtgbyhn")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to exclude the extension and the directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            excludes: vec![
+                glob::Pattern::new("*.tmp").unwrap(),
+                glob::Pattern::new("build").unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the file that isn't excluded is analyzed.
+        assert_eq!(1, dupes.num_files());
+        assert_eq!(0, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_exclude_anchored() {
+        // Given a directory with a file, a build directory with a copy, and
+        // a build directory within another with a copy,
+        let target_dir = Path::new("./target/test_dir/exclude_anchored");
+        let _ = fs::remove_dir_all(target_dir);
+        let build_dir = target_dir.join("build");
+        let nested_build_dir = target_dir.join("src").join("build");
+        create_dir_all(&build_dir);
+        create_dir_all(&nested_build_dir);
+
+        for path in &[
+            target_dir.join("a.txt"),
+            build_dir.join("b.txt"),
+            nested_build_dir.join("c.txt"),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of anchored excludes. ujmikolp")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration excludes the build directory at the top, as
+        // given by --exclude /build,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            anchored_excludes: vec![glob::Pattern::new("build").unwrap()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only that build directory is left out, not the nested one.
+        let group = dupes.into_iter().next().expect("Expected a group.");
+        assert_eq!(
+            &vec![target_dir.join("a.txt"), nested_build_dir.join("c.txt")],
+            group.1
+        );
+    }
+
+    #[test]
+    fn test_run_exclude_size_range() {
+        // Given a directory with pairs of duplicates of 10, 20, and 30 bytes,
+        let target_dir = Path::new("./target/test_dir/exclude_size_range");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        for len in &[10, 20, 30] {
+            for copy in &["a", "b"] {
+                let mut file = File::create(target_dir.join(format!("{}{}", copy, len))).unwrap();
+                file.write_all(&vec![b'x'; *len])
+                    .expect("Could not write data for file.");
+            }
+        }
+
+        // and the configuration excludes overlapping ranges covering 10 and 20,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            excluded_sizes: vec![(5, 15), (12, 20), (18, 19)],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the files outside of the ranges are compared.
+        assert_eq!(2, dupes.num_files());
+        let lens: Vec<u64> = dupes
+            .into_iter()
+            .map(|(lenhash, _)| lenhash.len())
+            .collect();
+        assert_eq!(vec![30], lens);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_write_catalog() {
+        // Given a directory with two identical files and one of another size,
+        let target_dir = Path::new("./target/test_dir/catalog");
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a.txt", &b"Contents for a catalog test. ujmnhy"[..]),
+            ("b.txt", &b"Contents for a catalog test. ujmnhy"[..]),
+            ("c.txt", &b"Other contents."[..]),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // When the catalog is written twice to the same database,
+        let db = Path::new("./target/test_dir/catalog.db");
+        let _ = std::fs::remove_file(db);
+        write_catalog(&dupes, db).expect("Could not write catalog.");
+        write_catalog(&dupes, db).expect("Could not write catalog again.");
+
+        // Then each file and group is in it once, and only the unique file has no hash.
+        let conn = rusqlite::Connection::open(db).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(3, count("SELECT COUNT(*) FROM files"));
+        assert_eq!(1, count("SELECT COUNT(*) FROM files WHERE hash IS NULL"));
+        assert_eq!(1, count("SELECT COUNT(*) FROM groups"));
+        assert_eq!(2, count("SELECT count FROM groups"));
+    }
+
+    #[test]
+    fn test_order_groups_stable() {
+        // Given two directories with a copy of the same file in each, and one more copy,
+        let target_dir = Path::new("./target/test_dir/order_stable");
+        create_dir_all(target_dir);
+        let first_dir = target_dir.join("z-first");
+        let second_dir = target_dir.join("a-second");
+        std::fs::create_dir(&first_dir).unwrap();
+        std::fs::create_dir(&second_dir).unwrap();
+
+        let paths = [
+            second_dir.join("a.txt"),
+            first_dir.join("c.txt"),
+            first_dir.join("b.txt"),
+        ];
+        for path in &paths {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of stable ordering. rfvtgbyhn")
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration lists the "z-first" directory first,
+        let config = Config {
+            dirs: vec![first_dir.clone(), second_dir.clone()],
+            ..Default::default()
+        };
+
+        // When the files are visited out of order, and the groups are ordered,
+        let mut dupes = AllInFileVisitor::new(&config);
+        for path in &paths {
+            dupes.visit(path.to_path_buf());
+        }
+        dupes.order_groups();
+
+        // Then the files under the first directory come first, each in alphabetical order.
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(
+            &vec![
+                first_dir.join("b.txt"),
+                first_dir.join("c.txt"),
+                second_dir.join("a.txt")
+            ],
+            group.1
+        );
+    }
+
+    #[test]
+    fn test_run_normalize_text() {
+        // Given a directory with text files that only differ by line endings and trailing spaces,
+        // and two binary files that would be the same if normalized,
+        let target_dir = Path::new("./target/test_dir/normalize_text");
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a.txt", &b"first line\nsecond line\n"[..]),
+            ("b.txt", &b"first line  \r\nsecond line\r\n\r\n"[..]),
+            ("c.bin", &b"\0binary\n"[..]),
+            ("d.bin", &b"\0binary\r\n"[..]),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and the configuration is to compare normalized text,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            normalize_text: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the text files are grouped.
+        let groups: Vec<_> = dupes.into_iter().collect();
+        assert_eq!(1, groups.len());
+        assert_eq!(
+            &vec![target_dir.join("a.txt"), target_dir.join("b.txt")],
+            groups[0].1
+        );
+    }
+
+    #[test]
+    fn test_wasted_by_extension() {
+        // Given a directory with three copies of a jpg, and two copies of a file with no extension,
+        let target_dir = Path::new("./target/test_dir/by_extension");
+        create_dir_all(target_dir);
+
+        for (name, data) in &[
+            ("a.jpg", &b"Contents of a picture. qscwdvefb"[..]),
+            ("b.JPG", &b"Contents of a picture. qscwdvefb"[..]),
+            ("c.jpg", &b"Contents of a picture. qscwdvefb"[..]),
+            ("d", &b"No extension."[..]),
+            ("e", &b"No extension."[..]),
+        ] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed and bucketed by extension,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let report = wasted_by_extension(&dupes);
+
+        // Then the jpg duplicates, regardless of case, waste the most.
+        assert_eq!(
+            vec![("jpg".to_string(), 2, 64), ("(none)".to_string(), 1, 13)],
+            report
+        );
+    }
+
+    #[test]
+    fn test_run_breadth_first() {
+        // Given a directory tree with files at several depths,
+        let target_dir = Path::new("./target/test_dir/breadth_first");
+        create_dir_all(target_dir);
+        let deep_dir = target_dir.join("a").join("b");
+        std::fs::create_dir_all(&deep_dir).unwrap();
+
+        let paths = [
+            deep_dir.join("deep.txt"),
+            target_dir.join("a").join("mid.txt"),
+            target_dir.join("z.txt"),
+        ];
+        for path in &paths {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a breadth first test. plokmijnu")
+                .expect("Could not write data for file.");
         }
+
+        // and the configuration is to walk it breadth first,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            breadth_first: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then the same files are found as when walking depth first,
+        assert_eq!(3, dupes.num_files());
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(3, group.1.len());
+
+        // and they are visited shallowest first.
+        let mut visited = VisitedPaths(Vec::new());
+        visit_dirs_breadth_first(
+            target_dir,
+            &mut visited,
+            &WalkState::new(&config, &AtomicBool::new(false)),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![paths[2].clone(), paths[1].clone(), paths[0].clone()],
+            visited.0
+        );
     }
 
-    Ok(dups)
-}
+    #[test]
+    fn test_run_no_recurse() {
+        // Given two directories, each with a file and a subdirectory with another file,
+        let target_dir = Path::new("./target/test_dir/no_recurse");
+        create_dir_all(target_dir);
+        let dirs = [target_dir.join("one"), target_dir.join("two")];
+        for dir in &dirs {
+            std::fs::create_dir_all(dir.join("sub")).unwrap();
+            for path in &[dir.join("top.txt"), dir.join("sub").join("deep.txt")] {
+                let mut file = File::create(path).unwrap();
+                file.write_all(b"Contents for a test of not recursing. qazwsxedc")
+                    .expect("Could not write data for file.");
+            }
+        }
 
-pub fn print_results(dups: &AllInFileVisitor) {
-    let mut num_dups = 0;
-    let mut dup_bytes: u64 = 0;
-    for x in dups {
-        println!(
-            "\nSize: {}  Hash: {}",
-            friendly_bytes(x.0.len()),
-            x.0.to_hex()
+        // and the configuration is to not descend into subdirectories,
+        let config = Config {
+            dirs: dirs.to_vec(),
+            no_recurse: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for both directories,
+        let dupes = run(&config).expect("Could not analyze directories.");
+
+        // Then only the top files of each are compared.
+        assert_eq!(2, dupes.num_files());
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(
+            vec![dirs[0].join("top.txt"), dirs[1].join("top.txt")],
+            *group.1
         );
-        for y in x.1 {
-            println!("{}", y.to_string_lossy());
-        }
-        num_dups += x.1.len() - 1;
-        dup_bytes += (x.1.len() - 1) as u64 * (x.0.len() as u64);
-    }
-    eprintln!(
-        "{} files, {} analyzed.",
-        &dups.num_files(),
-        friendly_bytes(dups.total_file_bytes())
-    );
-    eprintln!(
-        "{} duplicate files, {} of duplicates.",
-        &num_dups,
-        friendly_bytes(dup_bytes)
-    );
-
-    eprintln!("{} sets of duplicates.", dups.into_iter().count());
-}
+    }
 
-fn friendly_bytes(bytes: u64) -> String {
-    if bytes >= 1 << 30 {
-        let value = (bytes as f64) / (1024 * 1024 * 1024) as f64;
-        return format!("{:.1} GB", value);
-    } else if bytes >= 1 << 20 {
-        let value = (bytes as f64) / (1024 * 1024) as f64;
-        return format!("{:.1} MB", value);
-    } else if bytes >= 1 << 10 {
-        let value = (bytes as f64) / 1024.0;
-        return format!("{:.1} kB", value);
-    }
-    format!("{} B", bytes)
-}
+    #[test]
+    fn test_run_precount() {
+        // Given a directory with two identical files in a subdirectory,
+        let target_dir = Path::new("./target/test_dir/precount");
+        create_dir_all(target_dir);
+        std::fs::create_dir_all(target_dir.join("sub")).unwrap();
+        for name in &["a.txt", "b.txt"] {
+            let mut file = File::create(target_dir.join("sub").join(name)).unwrap();
+            file.write_all(b"Contents for a test of counting first. tgbyhnujm")
+                .expect("Could not write data for file.");
+        }
 
-fn visit_dirs(dir: &Path, visitor: &mut dyn FileVisitor, config: &Config) -> io::Result<()> {
-    let dir_iter = fs::read_dir(dir)?;
-    for entry in dir_iter {
-        match entry {
-            Ok(entry) => {
-                let path = entry.path();
-                match entry.metadata() {
-                    Ok(metadata) => {
-                        // Only visit real (non-symlinked) directories
-                        if path.is_dir() && metadata.is_dir() {
-                            if let Err(e) = visit_dirs(&path, visitor, config) {
-                                eprintln!("Skipping directory {:?}.\nReason: {}", path, e);
-                            }
-                        } else if metadata.is_file() {
-                            visitor.visit(path);
-                        } else {
-                            eprintln!(
-                                "Skipping {:?}. It is not a directory or regular file.",
-                                path
-                            );
-                        }
-                    }
-                    Err(e) => eprintln!("Skipping {:?}.\nReason: {}", entry, e),
-                }
+        // and the configuration is to count the files first, while following
+        // symlinks, which tracks the directories already read,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            precount: true,
+            follow_symlinks: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then counting didn't keep the directories from being read again.
+        assert_eq!(2, dupes.num_files());
+        assert_eq!(1, dupes.into_iter().count());
+    }
+
+    #[test]
+    fn test_run_io_threads() {
+        // Given a directory with groups of files of a few sizes, and a file
+        // of the same size as one group but different contents,
+        let target_dir = Path::new("./target/test_dir/io_threads");
+        create_dir_all(target_dir);
+        let contents: [&[u8]; 4] = [
+            b"first group",
+            b"second group!",
+            b"third group of files",
+            b"first grOup",
+        ];
+        for (i, data) in contents.iter().enumerate() {
+            for copy in 0..(if i == 3 { 1 } else { 3 }) {
+                let mut file =
+                    File::create(target_dir.join(format!("{}-{}.txt", i, copy))).unwrap();
+                file.write_all(data)
+                    .expect("Could not write data for file.");
             }
-            Err(e) => eprintln!("Skipping entry in directory {:?}.\nReason: {}", dir, e),
         }
+
+        // When dupes are analyzed reading one file at a time, and four at once,
+        let serial = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let parallel = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            io_threads: 4,
+            ..Default::default()
+        };
+        let serial_dupes = run(&serial).expect("Could not analyze directory.");
+        let parallel_dupes = run(&parallel).expect("Could not analyze directory.");
+
+        // Then the same groups are found either way.
+        assert_eq!(3, parallel_dupes.into_iter().count());
+        assert_eq!(10, parallel_dupes.num_hashed());
+        assert!(serial_dupes.into_iter().eq(parallel_dupes.into_iter()));
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::prelude::*;
+    #[test]
+    fn test_run_manifest() {
+        // Given a directory with files of different sizes, so none would
+        // usually be hashed,
+        let target_dir = Path::new("./target/test_dir/manifest");
+        create_dir_all(target_dir);
+        for (name, data) in &[("b.txt", &b"bb"[..]), ("a.txt", &b"a"[..])] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
 
-    fn create_dir_all(target_dir: &Path) {
-        std::fs::create_dir_all(target_dir).unwrap_or_else(|error| {
-            if error.kind() != io::ErrorKind::AlreadyExists {
-                panic!("Problem creating directory: {:?}", error);
-            }
-        });
+        // and the configuration is to write a manifest,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            manifest: true,
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then every file is listed with its hash and size, ordered by path.
+        let a = hash_contents_path(&target_dir.join("a.txt")).unwrap();
+        let b = hash_contents_path(&target_dir.join("b.txt")).unwrap();
+        let expected = format!(
+            "{} 1 {}\n{} 2 {}\n",
+            a.to_hex(),
+            target_dir.join("a.txt").display(),
+            b.to_hex(),
+            target_dir.join("b.txt").display()
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
     }
 
     #[test]
-    fn test_run() {
-        // Given a directory with two files,
-        let target_dir = Path::new("./target/test_dir/two_files");
+    fn test_print_results_match_manifest() {
+        // Given a directory with files of different sizes, so none would
+        // usually be hashed,
+        let target_dir = Path::new("./target/test_dir/match_manifest");
+        create_dir_all(target_dir);
+        for (name, data) in &[("a.txt", &b"a"[..]), ("b.txt", &b"bb"[..])] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        }
+
+        // and a sha256sum manifest with the contents of one of them,
+        let manifest = format!(
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb  known/a.txt\n\
+             {}  known/other.txt\n",
+            "00".repeat(32)
+        );
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            hashes: vec![HashAlgorithm::Sha256],
+            match_manifest: Some(checksums::parse(&manifest, HashAlgorithm::Sha256).unwrap()),
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then only the file with the same contents is listed, with the
+        // file of the manifest it matches.
+        let expected = format!("{}\n\tknown/a.txt\n", target_dir.join("a.txt").display());
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+
+        // And with --path-encoding base64, both paths are encoded.
+        let config = Config {
+            path_encoding: PathEncoding::Base64,
+            ..config
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+        let expected = format!(
+            "{}\n\t{}\n",
+            base64::encode(target_dir.join("a.txt").to_string_lossy().as_bytes()),
+            base64::encode(b"known/a.txt")
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+
+        // But nothing matches when hashed with blake3 instead.
+        let config = Config {
+            hashes: vec![HashAlgorithm::Blake3],
+            path_encoding: PathEncoding::Lossy,
+            ..config
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_run_since() {
+        // Given a directory with three identical files,
+        let target_dir = Path::new("./target/test_dir/since");
         create_dir_all(target_dir);
 
-        // and both files are identical,
-        let orig_path = target_dir.join(Path::new("a.txt"));
-        {
-            let mut original = File::create(&orig_path).unwrap();
-            original
-                .write_all(b"Contents for a test of two files of identical content. This should create a \"duplicate group\" with one file being marked as original, and the other as a duplicate.")
+        for name in &["a.txt", "b.txt", "c.txt"] {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(b"Contents for a test of reusing hashes. rfvtgbyhn")
                 .expect("Could not write data for file.");
         }
-        let dupe_path = target_dir.join(Path::new("b.txt"));
-        let _ = std::fs::copy(orig_path.as_path(), dupe_path.as_path())
-            .expect("Could not write data for file.");
 
-        // and the configuration is to analyze that directory,
+        // and the result of scanning it as ndjson,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Ndjson,
+            ..Default::default()
         };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+        let since =
+            prior::parse(&String::from_utf8(out).unwrap()).expect("Could not parse result.");
+        assert_eq!(3, since.len());
 
-        // When dupes are analyzed for that directory,
+        // and since then, one file was modified and another removed,
+        File::options()
+            .write(true)
+            .open(target_dir.join("b.txt"))
+            .and_then(|file| file.set_modified(std::time::SystemTime::UNIX_EPOCH))
+            .expect("Could not set modification time.");
+        std::fs::remove_file(target_dir.join("c.txt")).unwrap();
+
+        // When dupes are analyzed again with the earlier result,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            since: Some(since),
+            ..Default::default()
+        };
         let dupes = run(&config).expect("Could not analyze directory.");
 
-        // Then one group with two files should be listed,
-        // and "a.txt" is the original and "b.txt" is the duplicate because
-        // alphabetically a.txt comes first.
-        assert_eq!(1, dupes.into_iter().count());
-        let mut iter = dupes.into_iter();
-        let group = iter.next().unwrap();
-        assert_eq!(2, group.1.len());
-        assert_eq!(orig_path.as_path(), group.1[0].as_path());
-        assert_eq!(dupe_path.as_path(), group.1[1].as_path());
-        assert!(iter.next().is_none(), "Only one dupe group should exist.");
+        // Then only the unchanged file's hash is reused, and the removed file is gone.
+        assert_eq!(1, dupes.num_reused());
+        assert_eq!(2, dupes.num_hashed());
+        let group = dupes.into_iter().next().unwrap();
+        assert_eq!(
+            vec![target_dir.join("a.txt"), target_dir.join("b.txt")],
+            *group.1
+        );
+    }
+
+    #[test]
+    fn test_print_results_groups_from() {
+        // Given a directory with two pairs of identical files,
+        let target_dir = Path::new("./target/test_dir/groups_from");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+
+        let write = |name: &str, data: &[u8]| {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        };
+        write("a1.txt", b"Contents of the group that stays. edcrfv");
+        write("a2.txt", b"Contents of the group that stays. edcrfv");
+        write("b1.txt", b"Contents of the group that is resolved. tgb");
+        write("b2.txt", b"Contents of the group that is resolved. tgb");
+
+        // and the result of scanning it as ndjson,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            format: Format::Ndjson,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+        let groups_from =
+            prior::parse_groups(&String::from_utf8(out).unwrap()).expect("Could not parse result.");
+        assert_eq!(2, groups_from.len());
+
+        // and since then, one copy was removed and a new pair was added,
+        fs::remove_file(target_dir.join("b2.txt")).unwrap();
+        write("c1.txt", b"Contents of the new group. yhn");
+        write("c2.txt", b"Contents of the new group. yhn");
+
+        // When the results are printed compared with the earlier result,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            groups_from: Some(groups_from),
+            no_header: true,
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then each group is listed under whether it is still, newly, or no
+        // longer duplicated.
+        let out = String::from_utf8(out).unwrap();
+        let b_hash = blake3::hash(b"Contents of the group that is resolved. tgb").to_hex();
+        let expected = format!(
+            "Still duplicated:\n\n{dir}/a1.txt\n{dir}/a2.txt\n\n\
+             Newly duplicated:\n\n{dir}/c1.txt\n{dir}/c2.txt\n\n\
+             Resolved:\n\nSize: 43 B  Hash: {hash}\n{dir}/b1.txt\n{dir}/b2.txt\n",
+            dir = target_dir.display(),
+            hash = b_hash
+        );
+        assert_eq!(expected, out);
     }
 
     #[test]
@@ -171,8 +3040,8 @@ mod tests {
 
         // and the configuration is to analyze that directory,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -207,8 +3076,8 @@ mod tests {
 
         // and the configuration is to analyze that directory,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -235,8 +3104,8 @@ mod tests {
 
         // and the configuration is to analyze that directory,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -254,8 +3123,8 @@ mod tests {
 
         // and the configuration is to analyze that directory,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -263,6 +3132,7 @@ mod tests {
 
         // Then no files should be listed, since a directory with zero files cannot have a dupe.
         assert_eq!(0, dupes.into_iter().count());
+        assert!(dupes.largest_group().is_none());
     }
 
     #[test]
@@ -272,8 +3142,8 @@ mod tests {
 
         // and the configuration is to analyze that directory,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -284,29 +3154,49 @@ mod tests {
     }
 
     #[test]
-    fn test_run_non_dir_path() {
-        // Given a file instead of a directory,
-        let target_dir = Path::new("./target/test_dir/non_dir_path");
+    fn test_run_file_path() {
+        // Given a directory with a file, and a copy of that file outside of the directory,
+        let target_dir = Path::new("./target/test_dir/file_path");
         create_dir_all(target_dir);
+        let sub_dir = target_dir.join("sub");
+        std::fs::create_dir(&sub_dir).unwrap();
 
-        let non_dir_path = target_dir.join(Path::new("non_dir"));
-        {
-            let mut non_dir = File::create(&non_dir_path).unwrap();
-            non_dir
-                .write_all(b"This is a file, not a directory.")
+        let file_path = target_dir.join("a.txt");
+        for path in &[file_path.clone(), sub_dir.join("b.txt")] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"This is a file, not a directory.")
                 .expect("Could not write data for file.");
         }
 
-        // and the configuration is to analyze that file,
+        // and the configuration is to analyze that file and the directory,
         let config = Config {
-            dir: non_dir_path.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![file_path.clone(), sub_dir.clone()],
+            ..Default::default()
         };
 
-        // When dupes are analyzed for that non-directory,
-        // Then an error is returned indicating the path given is not to a directory.
-        let err = run(&config)
-            .expect_err("Should error when attempting to analyze a path that isn't a directory.");
+        // When dupes are analyzed for them,
+        let dupes = run(&config).expect("Could not analyze paths.");
+
+        // Then the file is compared with the file in the directory.
+        let group = dupes.into_iter().next().expect("Expected a group.");
+        assert_eq!(vec![file_path, sub_dir.join("b.txt")], *group.1);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_non_dir_path() {
+        // Given a path that is neither a directory nor a regular file,
+        // and the configuration is to analyze that path,
+        let config = Config {
+            dirs: vec![PathBuf::from("/dev/null")],
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that path,
+        // Then an error is returned indicating the path can't be analyzed.
+        let err = run(&config).expect_err(
+            "Should error when attempting to analyze a path that isn't a directory or file.",
+        );
         assert_eq!(io::ErrorKind::Other, err.kind());
     }
 
@@ -336,8 +3226,8 @@ mod tests {
 
         // and the configuration is to analyze that directory, not listing hardlinks as duplicates,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -391,8 +3281,8 @@ mod tests {
 
         // and the configuration is to analyze that directory, not listing hardlinks as duplicates,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -407,7 +3297,140 @@ mod tests {
         assert_eq!(orig_path.as_path(), group.1[0].as_path());
         assert_eq!(dupe_path.as_path(), group.1[1].as_path());
         assert!(iter.next().is_none(), "Only one dupe group should exist.");
+
+        // and hardlinking would save one more copy, beyond the two already shared by hardlinks.
+        assert_eq!(data.len() as u64, hardlink_savings(group.0, group.1));
+        assert_eq!(2 * data.len() as u64, dupes.linked_bytes());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_hard_link_shortest() {
+        // Given a file, a hard link to it with a shorter path that is found
+        // after it, and a copy of it,
+        let target_dir = Path::new("./target/test_dir/hard_link_shortest");
+        create_dir_all(target_dir);
+
+        let data = b"Contents for a test of which hard link is listed. qpwoeiru";
+        let orig_path = target_dir.join("a-longer-name.txt");
+        let mut file = File::create(&orig_path).unwrap();
+        file.write_all(data)
+            .expect("Could not write data for file.");
+        let link_path = target_dir.join("z.txt");
+        std::fs::hard_link(&orig_path, &link_path).unwrap();
+        let copy_path = target_dir.join("b.txt");
+        let mut file = File::create(&copy_path).unwrap();
+        file.write_all(data)
+            .expect("Could not write data for file.");
+
+        // When dupes are analyzed for that directory, walked either way,
+        for threads in &[1, 4] {
+            let config = Config {
+                dirs: vec![target_dir.to_path_buf()],
+                threads: *threads,
+                ..Default::default()
+            };
+            let dupes = run(&config).expect("Could not analyze directory.");
+
+            // Then the link with the shorter path stands for the data, rather
+            // than the first one found.
+            let groups: Vec<_> = dupes.into_iter().map(|(_, paths)| paths.clone()).collect();
+            assert_eq!(vec![vec![copy_path.clone(), link_path.clone()]], groups);
+            assert_eq!(data.len() as u64, dupes.linked_bytes());
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_count_hardlinks() {
+        // Given a file with a hard link to it, and nothing else with its contents,
+        let target_dir = Path::new("./target/test_dir/count_hardlinks");
+        create_dir_all(target_dir);
+
+        let write = |name: &str, data: &[u8]| {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        };
+        write("a.txt", b"Only hard linked. qwertyu");
+        std::fs::hard_link(target_dir.join("a.txt"), target_dir.join("a.txt-hardlink")).unwrap();
+
+        // and another file with a hard link to it, and a real copy of it,
+        write("b.txt", b"Hard linked and copied. asdfghj");
+        std::fs::hard_link(target_dir.join("b.txt"), target_dir.join("b.txt-hardlink")).unwrap();
+        write("c.txt", b"Hard linked and copied. asdfghj");
+
+        // and the configuration is to list hard links as duplicates,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            count_hardlinks: true,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the group with a real copy is listed, with the hard link in it.
+        let groups: Vec<_> = dupes.into_iter().collect();
+        assert_eq!(1, groups.len());
+        assert_eq!(
+            &vec![
+                target_dir.join("b.txt"),
+                target_dir.join("b.txt-hardlink"),
+                target_dir.join("c.txt")
+            ],
+            groups[0].1
+        );
+
+        // and only the real copy is counted as wasting space.
+        assert_eq!(1, dupes.num_duplicate_files());
+        assert_eq!(31, dupes.wasted_bytes());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_print_results_count_hardlinks_wasted() {
+        // Given two files, each with a hard link to it and a real copy of it,
+        let target_dir = Path::new("./target/test_dir/count_hardlinks_wasted");
+        create_dir_all(target_dir);
+
+        let write = |name: &str, data: &[u8]| {
+            let mut file = File::create(target_dir.join(name)).unwrap();
+            file.write_all(data)
+                .expect("Could not write data for file.");
+        };
+        write("a.txt", &[b'a'; 40]);
+        std::fs::hard_link(target_dir.join("a.txt"), target_dir.join("a.txt-hardlink")).unwrap();
+        write("b.txt", &[b'a'; 40]);
+        write("c.txt", &[b'c'; 20]);
+        std::fs::hard_link(target_dir.join("c.txt"), target_dir.join("c.txt-hardlink")).unwrap();
+        write("d.txt", &[b'c'; 20]);
+
+        // and the configuration is to list hard links as duplicates, and only
+        // groups wasting at least 35 bytes,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            count_hardlinks: true,
+            min_waste: Some(35),
+            ..Default::default()
+        };
+
+        // When the results are printed,
+        let dupes = run(&config).expect("Could not analyze directory.");
+        let mut out = Vec::new();
+        print_results(&mut out, &dupes, &config);
+
+        // Then the group with the hard link is listed as wasting the one real
+        // copy, as the summary counts it,
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Size: 40 B  Wasted: 40 B  "), "{}", out);
+        assert_eq!(40 + 20, dupes.wasted_bytes());
+
+        // and the group of the smaller file is left out, its one real copy
+        // wasting less than 35 bytes.
+        assert!(!out.contains("c.txt"), "{}", out);
     }
+
     #[cfg(target_family = "unix")]
     #[test]
     fn test_run_symlink() {
@@ -417,7 +3440,7 @@ mod tests {
         create_dir_all(target_dir);
 
         let orig_file = Path::new("a.txt");
-        let orig_path = target_dir.join(&orig_file);
+        let orig_path = target_dir.join(orig_file);
         {
             let mut original = File::create(&orig_path).unwrap();
             original
@@ -427,7 +3450,7 @@ mod tests {
 
         // and another file is symlinked to that data,
         let hlink_path = target_dir.join(Path::new("a-symlink.txt"));
-        std::os::unix::fs::symlink(&orig_file, &hlink_path).unwrap_or_else(|error| {
+        std::os::unix::fs::symlink(orig_file, &hlink_path).unwrap_or_else(|error| {
             if error.kind() != io::ErrorKind::AlreadyExists {
                 panic!("Problem creating symlink: {:?}", error);
             }
@@ -435,8 +3458,8 @@ mod tests {
 
         // and the configuration is to analyze that directory, not inspecting symlinked files or directories,
         let config = Config {
-            dir: target_dir.to_string_lossy().to_string(),
-            verbosity: 0,
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
         };
 
         // When dupes are analyzed for that directory,
@@ -444,5 +3467,200 @@ mod tests {
 
         // Then no files should be listed, since only the original file and a symlink were found.
         assert_eq!(0, dupes.into_iter().count());
+        assert_eq!(0, dupes.broken_symlinks());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_symlinked_dir() {
+        // Given a directory with two identical files, and a subdirectory with
+        // a third copy,
+        let target_dir = Path::new("./target/test_dir/symlinked_dir");
+        let _ = fs::remove_dir_all(target_dir);
+        let real_dir = target_dir.join("real");
+        create_dir_all(&real_dir.join("sub"));
+
+        for path in &[
+            real_dir.join("a.txt"),
+            real_dir.join("b.txt"),
+            real_dir.join("sub").join("c.txt"),
+        ] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of a symlinked dir. plokmijn")
+                .expect("Could not write data for file.");
+        }
+
+        // and a symlink to the directory, and within it one to the subdirectory,
+        let link_dir = target_dir.join("link");
+        std::os::unix::fs::symlink("real", &link_dir).unwrap();
+        std::os::unix::fs::symlink("sub", real_dir.join("sub-link")).unwrap();
+
+        for threads in &[1, 2] {
+            // and the configuration is to analyze the symlink, not following symlinks,
+            let config = Config {
+                dirs: vec![link_dir.clone()],
+                threads: *threads,
+                ..Default::default()
+            };
+
+            // When dupes are analyzed for that symlink,
+            let dupes = run(&config).expect("Could not analyze directory.");
+
+            // Then the directory it links to is scanned, but the symlink
+            // within it isn't followed, so the subdirectory is only read once.
+            assert_eq!(3, dupes.num_files());
+            let group = dupes.into_iter().next().unwrap();
+            assert_eq!(
+                vec![
+                    link_dir.join("a.txt"),
+                    link_dir.join("b.txt"),
+                    link_dir.join("sub").join("c.txt")
+                ],
+                *group.1
+            );
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_follow_symlinks_loop() {
+        // Given a directory with a file, and a subdirectory with a copy of the file,
+        let target_dir = Path::new("./target/test_dir/sym_link_loop");
+        create_dir_all(target_dir);
+        let sub_dir = target_dir.join("sub");
+        std::fs::create_dir(&sub_dir).unwrap();
+
+        for path in &[target_dir.join("a.txt"), sub_dir.join("b.txt")] {
+            let mut file = File::create(path).unwrap();
+            file.write_all(b"Contents for a test of symlink loops. tgbyhnujm")
+                .expect("Could not write data for file.");
+        }
+
+        // and the subdirectory has a symlink back to its parent, and one to the file,
+        std::os::unix::fs::symlink("..", sub_dir.join("parent")).unwrap();
+        std::os::unix::fs::symlink("../a.txt", sub_dir.join("c-symlink.txt")).unwrap();
+
+        for threads in 1..=2 {
+            // and the configuration is to follow symlinks,
+            let config = Config {
+                dirs: vec![target_dir.to_path_buf()],
+                follow_symlinks: true,
+                threads,
+                ..Default::default()
+            };
+
+            // When dupes are analyzed for that directory,
+            let dupes = run(&config).expect("Could not analyze directory.");
+
+            // Then the walk finishes, and each file is only counted once.
+            assert_eq!(2, dupes.num_files());
+            let groups: Vec<_> = dupes.into_iter().collect();
+            assert_eq!(1, groups.len());
+            assert_eq!(2, groups[0].1.len());
+        }
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_special_files() {
+        use std::os::unix::ffi::OsStringExt;
+
+        // Given a directory with a file, a fifo, and a socket,
+        let target_dir = Path::new("./target/test_dir/special_files");
+        let _ = fs::remove_dir_all(target_dir);
+        create_dir_all(target_dir);
+        File::create(target_dir.join("a.txt"))
+            .and_then(|mut file| file.write_all(b"Contents next to special files. lpokmn"))
+            .expect("Could not write data for file.");
+        let fifo =
+            std::ffi::CString::new(target_dir.join("fifo").into_os_string().into_vec()).unwrap();
+        assert_eq!(0, unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) });
+        let _socket = std::os::unix::net::UnixListener::bind(target_dir.join("socket"))
+            .expect("Could not create socket.");
+
+        // When dupes are analyzed for that directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the file is analyzed, and the others are counted by kind.
+        assert_eq!(1, dupes.num_files());
+        let special_files = dupes.special_files();
+        assert_eq!(2, special_files.total());
+        assert_eq!(1, special_files.fifos);
+        assert_eq!(1, special_files.sockets);
+        assert_eq!("1 fifo, 1 socket", special_files.to_string());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_broken_symlink() {
+        // Given a directory with a file, and two symlinks to files that don't exist,
+        let target_dir = Path::new("./target/test_dir/broken_sym_links");
+        create_dir_all(target_dir);
+
+        let mut file = File::create(target_dir.join("a.txt")).unwrap();
+        file.write_all(b"Contents for a file next to broken symlinks. zaqxswcde")
+            .expect("Could not write data for file.");
+        for name in &["b-symlink.txt", "c-symlink.txt"] {
+            std::os::unix::fs::symlink("missing.txt", target_dir.join(name)).unwrap();
+        }
+
+        // and the configuration is to analyze that directory, with several threads,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            threads: 2,
+            ..Default::default()
+        };
+
+        // When dupes are analyzed for that directory,
+        let dupes = run(&config).expect("Could not analyze directory.");
+
+        // Then only the file is analyzed, and both broken symlinks are counted as skipped.
+        assert_eq!(1, dupes.num_files());
+        assert_eq!(2, dupes.broken_symlinks());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_run_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Given a directory with a file, and a subdirectory that can't be read,
+        let target_dir = Path::new("./target/test_dir/unreadable_dir");
+        create_dir_all(target_dir);
+        let locked = target_dir.join("locked");
+        std::fs::create_dir_all(&locked).unwrap();
+        let mut file = File::create(target_dir.join("a.txt")).unwrap();
+        file.write_all(b"Contents for a file next to an unreadable directory. vfrcdexsw")
+            .expect("Could not write data for file.");
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        let still_readable = std::fs::read_dir(&locked).is_ok();
+
+        // When dupes are analyzed for that directory,
+        let config = Config {
+            dirs: vec![target_dir.to_path_buf()],
+            ..Default::default()
+        };
+        let dupes = run(&config);
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // Then the scan still succeeds, and the directory is counted as unreadable,
+        // unless running as root, which can read it anyway.
+        let dupes = dupes.expect("Could not analyze directory.");
+        assert_eq!(1, dupes.num_files());
+        if !still_readable {
+            assert_eq!(1, dupes.unreadable_dirs().len());
+            assert_eq!(locked, dupes.unreadable_dirs()[0].0);
+        }
+
+        // but if the given directory itself can't be read, it is still an error.
+        let config = Config {
+            dirs: vec![target_dir.join("missing")],
+            ..Default::default()
+        };
+        assert!(run(&config).is_err());
     }
 }
@@ -1,4 +1,18 @@
+pub mod action;
+pub mod archive;
+pub mod base64;
+pub mod bytesize;
+pub mod catalog;
+pub mod checksums;
 pub mod config;
+pub mod configfile;
 pub mod devino;
+pub mod filevisitor;
+pub mod freespace;
+pub mod hashing;
 pub mod lenhash;
-pub mod filevisitor;
\ No newline at end of file
+pub mod mtime;
+pub mod output;
+pub mod pathlist;
+pub mod prior;
+pub mod skip;
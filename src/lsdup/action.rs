@@ -0,0 +1,357 @@
+use crate::lsdup::archive;
+use crate::lsdup::config::{Action, Config};
+use crate::lsdup::filevisitor::AllInFileVisitor;
+use crate::lsdup::output::{friendly_bytes, path_bytes};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{self, Path, PathBuf};
+
+// What an action did, or with --dry-run, would have done.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct ActionReport {
+    // Number of duplicate files acted on.
+    pub files: usize,
+    // Bytes of the duplicate files acted on.
+    pub bytes: u64,
+    // Number of duplicate files the action failed for.
+    pub failed: usize,
+}
+
+// Takes the configured action on the duplicates of each group, keeping the
+// first file of each group as the original.
+pub fn act_on_duplicates(dups: &AllInFileVisitor, config: &Config) -> ActionReport {
+    let mut report = ActionReport::default();
+    let action = match config.action {
+        Some(action) => action,
+        None => return report,
+    };
+    for (lenhash, paths) in dups {
+        let original = &paths[0];
+        for duplicate in &paths[1..] {
+            if config.dry_run {
+                println!("Would {} {:?} to {:?}", action.verb(), duplicate, original);
+            } else {
+                let result = match action {
+                    Action::Reflink => reflink(original, duplicate),
+                    Action::Delete => fs::remove_file(duplicate),
+                };
+                if let Err(e) = result {
+                    eprintln!(
+                        "Error: Could not {} {:?} to {:?}: {}",
+                        action.verb(),
+                        duplicate,
+                        original,
+                        e
+                    );
+                    report.failed += 1;
+                    continue;
+                }
+                if config.verbosity > 0 {
+                    eprintln!("{} {:?} to {:?}", action.past_tense(), duplicate, original);
+                }
+            }
+            report.files += 1;
+            report.bytes += lenhash.len();
+        }
+    }
+    report
+}
+
+// Lists the files of each group on out, numbered, and reads from input which
+// one to keep, then deletes the others. The answer can also be a to keep all
+// of them, or q (or the end of input) to keep all of the remaining groups.
+// Anything else is asked again.
+pub fn act_interactively(
+    dups: &AllInFileVisitor,
+    config: &Config,
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+) -> io::Result<ActionReport> {
+    let mut report = ActionReport::default();
+    'groups: for (lenhash, paths) in dups {
+        writeln!(
+            out,
+            "\n{} identical files of {}:",
+            paths.len(),
+            friendly_bytes(lenhash.len())
+        )?;
+        for (i, path) in paths.iter().enumerate() {
+            writeln!(out, "{:>4}) {}", i + 1, path.display())?;
+        }
+        let keep = loop {
+            write!(
+                out,
+                "Keep which file? [1-{}, a to keep all, q to quit] ",
+                paths.len()
+            )?;
+            out.flush()?;
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                break 'groups;
+            }
+            match answer.trim() {
+                "q" => break 'groups,
+                "a" => continue 'groups,
+                answer => match answer.parse::<usize>() {
+                    Ok(n) if n >= 1 && n <= paths.len() => break n - 1,
+                    _ => writeln!(
+                        out,
+                        "Please answer with a number from 1 to {}, a, or q.",
+                        paths.len()
+                    )?,
+                },
+            }
+        };
+        for (i, duplicate) in paths.iter().enumerate() {
+            if i == keep {
+                continue;
+            }
+            if config.dry_run {
+                writeln!(out, "Would delete {:?}", duplicate)?;
+            } else if let Err(e) = fs::remove_file(duplicate) {
+                eprintln!("Error: Could not delete {:?}: {}", duplicate, e);
+                report.failed += 1;
+                continue;
+            } else if config.verbosity > 0 {
+                eprintln!("Deleted {:?}", duplicate);
+            }
+            report.files += 1;
+            report.bytes += lenhash.len();
+        }
+    }
+    Ok(report)
+}
+
+// The shell a --script is written for.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum Shell {
+    Sh,
+    PowerShell,
+}
+
+impl Shell {
+    // The shell scripts are run with on this platform.
+    pub fn native() -> Shell {
+        if cfg!(target_family = "windows") {
+            Shell::PowerShell
+        } else {
+            Shell::Sh
+        }
+    }
+}
+
+// Writes a script to out that takes the configured action on the duplicates
+// of each group, or deletes them if there is none, keeping the first file of
+// each group as the original. Nothing is changed until the script is run, so
+// it can be reviewed first. Paths are written in full, so it can be run from
+// anywhere. Entries within archives can't be changed by a script, so they are
+// left out, and groups without at least two files are too.
+pub fn write_script(
+    dups: &AllInFileVisitor,
+    config: &Config,
+    shell: Shell,
+    out: &mut dyn Write,
+) -> io::Result<ActionReport> {
+    let mut report = ActionReport::default();
+    let action = config.action.unwrap_or(Action::Delete);
+    if shell == Shell::PowerShell && action == Action::Reflink {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reflinks can only be scripted for sh",
+        ));
+    }
+    if shell == Shell::Sh {
+        writeln!(out, "#!/bin/sh")?;
+    }
+    writeln!(
+        out,
+        "# Written by lsdup to {} the duplicates of each group, keeping the first file.",
+        action.verb()
+    )?;
+    writeln!(out, "# Review it before running it.")?;
+    for (lenhash, paths) in dups {
+        let files: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| !archive::is_entry(path))
+            .collect();
+        if files.len() < 2 {
+            continue;
+        }
+        let original = path::absolute(files[0])?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "# {} identical files of {}, keeping {:?}",
+            files.len(),
+            friendly_bytes(lenhash.len()),
+            original
+        )?;
+        for duplicate in &files[1..] {
+            let duplicate = path::absolute(duplicate)?;
+            match (shell, action) {
+                (Shell::Sh, Action::Delete) => {
+                    out.write_all(b"rm -- ")?;
+                    out.write_all(&sh_quote(&duplicate))?;
+                }
+                (Shell::Sh, Action::Reflink) => {
+                    out.write_all(b"cp --reflink=always -- ")?;
+                    out.write_all(&sh_quote(&original))?;
+                    out.write_all(b" ")?;
+                    out.write_all(&sh_quote(&duplicate))?;
+                }
+                (Shell::PowerShell, _) => {
+                    write!(
+                        out,
+                        "Remove-Item -LiteralPath {}",
+                        powershell_quote(&duplicate)
+                    )?;
+                }
+            }
+            writeln!(out)?;
+            report.files += 1;
+            report.bytes += lenhash.len();
+        }
+    }
+    Ok(report)
+}
+
+// Quotes the path for sh, in single quotes, within which nothing is special
+// except a single quote itself, which is ended, escaped, and started again.
+fn sh_quote(path: &Path) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &b in path_bytes(path).iter() {
+        if b == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+// Quotes the path for PowerShell, in single quotes, within which a quote is
+// doubled. PowerShell also takes the typographic single quotes as quotes.
+fn powershell_quote(path: &Path) -> String {
+    let mut quoted = String::from("'");
+    for c in path.to_string_lossy().chars() {
+        if let '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' = c {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+impl Action {
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::Reflink => "reflink",
+            Action::Delete => "delete",
+        }
+    }
+
+    fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Reflink => "Reflinked",
+            Action::Delete => "Deleted",
+        }
+    }
+
+    // What happens to the bytes of the duplicates acted on.
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            Action::Reflink => "shared",
+            Action::Delete => "freed",
+        }
+    }
+}
+
+// Replaces the duplicate with a copy-on-write clone of the original, so the
+// two share the same data on disk but can still be changed independently. The
+// clone is made next to the duplicate and then renamed over it, so the
+// duplicate is left as it was if cloning fails.
+pub fn reflink(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let temp = temp_path(duplicate);
+    let result = clone_to(original, &temp, duplicate).and_then(|_| fs::rename(&temp, duplicate));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn clone_to(original: &Path, temp: &Path, duplicate: &Path) -> io::Result<()> {
+    let permissions = fs::metadata(duplicate)?.permissions();
+    let src = File::open(original)?;
+    let dest = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    clone_file(&src, &dest)?;
+    fs::set_permissions(temp, permissions)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".lsdup-tmp");
+    PathBuf::from(temp)
+}
+
+#[cfg(target_os = "linux")]
+fn clone_file(src: &File, dest: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // _IOW(0x94, 9, int), from linux/fs.h
+    const FICLONE: u64 = 0x4004_9409;
+
+    let result = unsafe { libc::ioctl(dest.as_raw_fd(), FICLONE as _, src.as_raw_fd()) };
+    if result == -1 {
+        let e = io::Error::last_os_error();
+        return Err(match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::EINVAL) | Some(libc::ENOTTY) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the filesystem does not support reflinks",
+            ),
+            Some(libc::EXDEV) => io::Error::new(
+                io::ErrorKind::Unsupported,
+                "reflinks can't be made across filesystems",
+            ),
+            _ => e,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_src: &File, _dest: &File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sh_quote() {
+        assert_eq!(
+            b"'/dir/a b.txt'".to_vec(),
+            sh_quote(Path::new("/dir/a b.txt"))
+        );
+        assert_eq!(
+            b"'/dir/it'\\''s $HOME\n.txt'".to_vec(),
+            sh_quote(Path::new("/dir/it's $HOME\n.txt"))
+        );
+    }
+
+    #[test]
+    fn test_powershell_quote() {
+        assert_eq!("'C:\\a b.txt'", powershell_quote(Path::new("C:\\a b.txt")));
+        assert_eq!(
+            "'C:\\it''s \u{2019}\u{2019}$env.txt'",
+            powershell_quote(Path::new("C:\\it's \u{2019}$env.txt"))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+#[cfg(feature = "zip")]
+use crate::lsdup::mtime;
+use std::ffi::OsString;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Entries within archives are compared as if they were files, and are named
+// by the archive path and the path within the archive, joined by this.
+pub const ENTRY_SEPARATOR: &str = "!";
+
+// A regular file within an archive, as given to the visit of for_each_entry.
+pub struct Entry<'a> {
+    // The name for the entry, like "backup.zip!photos/a.jpg".
+    pub path: PathBuf,
+    // Its path within the archive, like "photos/a.jpg".
+    pub name: PathBuf,
+    pub size: u64,
+    // When it was last modified, if the archive says.
+    pub modified: Option<SystemTime>,
+    pub contents: &'a mut dyn Read,
+}
+
+impl Entry<'_> {
+    // True if the entry, or any directory it is in within the archive, is
+    // named with a leading dot. Archives don't keep the hidden attribute of
+    // Windows, so on every platform this is what hidden means for entries.
+    pub fn is_hidden(&self) -> bool {
+        self.name
+            .components()
+            .any(|part| part.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    // The paths within the archive of the directories the entry is in, and
+    // of the entry itself, outermost first, like "photos" and then
+    // "photos/a.jpg", to check against --exclude as a walk would.
+    pub fn ancestors(&self) -> Vec<&Path> {
+        let mut ancestors: Vec<&Path> = self
+            .name
+            .ancestors()
+            .filter(|name| !name.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        ancestors
+    }
+}
+
+// The kinds of archive that can have their entries scanned, told apart by
+// the file name.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+enum Kind {
+    Zip,
+    Tar,
+    // A gzip compressed tar, named .tar.gz or .tgz.
+    TarGz,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+    if name.ends_with(".zip") {
+        Some(Kind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else {
+        None
+    }
+}
+
+// True if the file is an archive that can have its entries scanned.
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+// The name for an entry within an archive, like "backup.zip!photos/a.jpg".
+pub fn entry_path(archive: &Path, entry: &Path) -> PathBuf {
+    let mut path = OsString::from(archive.as_os_str());
+    path.push(ENTRY_SEPARATOR);
+    path.push(entry);
+    PathBuf::from(path)
+}
+
+// True if the path names an entry within an archive rather than a file: part
+// of it up to a separator names an archive, and there is no such file.
+pub fn is_entry(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.match_indices(ENTRY_SEPARATOR)
+        .any(|(i, _)| is_archive(Path::new(&name[..i])))
+        && !path.exists()
+}
+
+// Calls visit with each regular file in the archive. Entries that can't be
+// read are skipped with a warning, as are directories, links, and anything
+// else that isn't a regular file.
+pub fn for_each_entry(archive: &Path, visit: &mut dyn FnMut(Entry)) -> io::Result<()> {
+    match kind(archive) {
+        Some(Kind::Zip) => for_each_zip_entry(archive, visit),
+        Some(Kind::Tar) => for_each_tar_entry(archive, false, visit),
+        Some(Kind::TarGz) => for_each_tar_entry(archive, true, visit),
+        None => Err(io::Error::other(format!("{:?} is not an archive", archive))),
+    }
+}
+
+#[cfg(feature = "zip")]
+fn for_each_zip_entry(archive: &Path, visit: &mut dyn FnMut(Entry)) -> io::Result<()> {
+    let file = std::fs::File::open(archive)?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| io::Error::other(e.to_string()))?;
+    for i in 0..zip.len() {
+        match zip.by_index(i) {
+            Ok(mut entry) => {
+                if entry.is_file() {
+                    let name = PathBuf::from(entry.name());
+                    // Zip times have no time zone, so they are taken as UTC.
+                    let time = entry.last_modified();
+                    let modified = mtime::from_parts(
+                        time.year() as u64,
+                        time.month() as u64,
+                        time.day() as u64,
+                        (
+                            time.hour() as u64,
+                            time.minute() as u64,
+                            time.second() as u64,
+                        ),
+                    );
+                    visit(Entry {
+                        path: entry_path(archive, &name),
+                        name,
+                        size: entry.size(),
+                        modified,
+                        contents: &mut entry,
+                    });
+                }
+            }
+            Err(e) => eprintln!(
+                "Warning: Skipping entry {} in archive {:?}: {}",
+                i, archive, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "zip"))]
+fn for_each_zip_entry(archive: &Path, _visit: &mut dyn FnMut(Entry)) -> io::Result<()> {
+    Err(io::Error::other(format!(
+        "{:?} can't be scanned, lsdup was built without zip support",
+        archive
+    )))
+}
+
+// Reads the entries in order as they come, so a compressed tar is only
+// decompressed once, and nothing is extracted to disk.
+#[cfg(feature = "tar")]
+fn for_each_tar_entry(
+    archive: &Path,
+    gzipped: bool,
+    visit: &mut dyn FnMut(Entry),
+) -> io::Result<()> {
+    let file = io::BufReader::new(std::fs::File::open(archive)?);
+    let contents: Box<dyn Read> = if gzipped {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut tar = tar::Archive::new(contents);
+    for (i, entry) in tar.entries()?.enumerate() {
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!(
+                    "Warning: Skipping entry {} in archive {:?}: {}",
+                    i, archive, e
+                );
+                continue;
+            }
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        match entry.path() {
+            Ok(name) => {
+                let name = name.into_owned();
+                let modified = entry
+                    .header()
+                    .mtime()
+                    .ok()
+                    .map(|secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+                visit(Entry {
+                    path: entry_path(archive, &name),
+                    name,
+                    size: entry.size(),
+                    modified,
+                    contents: &mut entry,
+                });
+            }
+            Err(e) => eprintln!(
+                "Warning: Skipping entry {} in archive {:?}: {}",
+                i, archive, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "tar"))]
+fn for_each_tar_entry(
+    archive: &Path,
+    _gzipped: bool,
+    _visit: &mut dyn FnMut(Entry),
+) -> io::Result<()> {
+    Err(io::Error::other(format!(
+        "{:?} can't be scanned, lsdup was built without tar support",
+        archive
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive(Path::new("dir/a.zip")));
+        assert!(is_archive(Path::new("dir/a.ZIP")));
+        assert!(is_archive(Path::new("a.tar")));
+        assert!(is_archive(Path::new("a.tar.gz")));
+        assert!(is_archive(Path::new("a.tgz")));
+        assert!(!is_archive(Path::new("a.gz")));
+        assert!(!is_archive(Path::new("a.txt")));
+        assert!(!is_archive(Path::new("tar")));
+    }
+
+    #[test]
+    fn test_is_entry() {
+        assert!(is_entry(Path::new("dir/a.zip!b.txt")));
+        assert!(is_entry(Path::new("a.tar.gz!dir/b!c.txt")));
+        assert!(!is_entry(Path::new("dir/wow!.txt")));
+        assert!(!is_entry(Path::new("dir/a.zip")));
+    }
+}
@@ -0,0 +1,80 @@
+// Standard base64, with padding, as used by --path-encoding base64 to write
+// paths that aren't valid Unicode into formats that must be.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, &'static str> {
+    const BAD_BASE64: &str = "not valid base64";
+
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return Err(BAD_BASE64);
+    }
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (i, chunk) in text.chunks(4).enumerate() {
+        let last = i == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(BAD_BASE64);
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&a| a == c).ok_or(BAD_BASE64)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding;
+        decoded.extend_from_slice(&[(n >> 16) as u8, (n >> 8) as u8, n as u8][..3 - padding]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!("", encode(b""));
+        assert_eq!("Zg==", encode(b"f"));
+        assert_eq!("Zm8=", encode(b"fo"));
+        assert_eq!("Zm9v", encode(b"foo"));
+        assert_eq!("Zm9vYg==", encode(b"foob"));
+        assert_eq!("/w==", encode(&[0xff]));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Ok(b"".to_vec()), decode(""));
+        assert_eq!(Ok(b"f".to_vec()), decode("Zg=="));
+        assert_eq!(Ok(b"foob".to_vec()), decode("Zm9vYg=="));
+        let bytes = b"dir/\xff\xfe\n.txt";
+        assert_eq!(Ok(bytes.to_vec()), decode(&encode(bytes)));
+    }
+
+    #[test]
+    fn test_decode_bad() {
+        assert!(decode("Zg=").is_err());
+        assert!(decode("Zg==Zg==").is_err());
+        assert!(decode("Z===").is_err());
+        assert!(decode("Zm9*").is_err());
+    }
+}
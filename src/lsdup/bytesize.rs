@@ -0,0 +1,85 @@
+// Parses a size given on the commandline: a whole number of bytes, optionally
+// followed by a unit. K, M, G, and T are powers of 1000, and KiB, MiB, GiB,
+// and TiB are powers of 1024. The unit is case-insensitive and the trailing B
+// is optional ("4k", "4KB", and "4000" are all the same size).
+pub fn parse(value: &str) -> Result<u64, &'static str> {
+    const BAD_SIZE: &str = "expected a number of bytes like 4096, 4KiB, or 8MB";
+
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let amount = value[..digits].parse::<u64>().map_err(|_| BAD_SIZE)?;
+    let unit = value[digits..].trim_start().to_ascii_lowercase();
+    let unit = unit.strip_suffix('b').unwrap_or(&unit);
+    let (prefix, base) = match unit.strip_suffix('i') {
+        Some(prefix) => (prefix, 1024u64),
+        None => (unit, 1000u64),
+    };
+    let power = match prefix {
+        "" if base == 1000 => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return Err(BAD_SIZE),
+    };
+    amount
+        .checked_mul(base.pow(power))
+        .ok_or("size is too large")
+}
+
+// Parses an inclusive range of sizes, written as MIN:MAX. Either end can be
+// left out, so ":1KiB" is anything up to 1 KiB and "1GB:" is anything from
+// 1 GB up.
+pub fn parse_range(value: &str) -> Result<(u64, u64), &'static str> {
+    let (min, max) = value
+        .split_once(':')
+        .ok_or("expected a range of sizes like 4KiB:8KiB")?;
+    let min = if min.trim().is_empty() {
+        0
+    } else {
+        parse(min)?
+    };
+    let max = if max.trim().is_empty() {
+        u64::MAX
+    } else {
+        parse(max)?
+    };
+    if min > max {
+        return Err("the smallest size of the range is larger than the largest");
+    }
+    Ok((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(4096), parse("4096"));
+        assert_eq!(Ok(4000), parse("4k"));
+        assert_eq!(Ok(4000), parse("4KB"));
+        assert_eq!(Ok(4096), parse("4KiB"));
+        assert_eq!(Ok(4096), parse("4 kib"));
+        assert_eq!(Ok(8_000_000), parse("8M"));
+        assert_eq!(Ok(3 << 30), parse("3GiB"));
+        assert_eq!(Ok(2_000_000_000_000), parse("2TB"));
+        assert!(parse("").is_err());
+        assert!(parse("4i").is_err());
+        assert!(parse("4XB").is_err());
+        assert!(parse("-4").is_err());
+        assert!(parse("99999999999TiB").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(Ok((4096, 8192)), parse_range("4KiB:8KiB"));
+        assert_eq!(Ok((0, 1000)), parse_range(":1k"));
+        assert_eq!(Ok((1_000_000_000, u64::MAX)), parse_range("1G:"));
+        assert_eq!(Ok((5, 5)), parse_range("5:5"));
+        assert!(parse_range("8k:4k").is_err());
+        assert!(parse_range("4k").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::lsdup::filevisitor::AllInFileVisitor;
+use std::io;
+use std::path::Path;
+
+// Writes every file scanned, and each group of duplicates, to the SQLite
+// database at the path, creating it if needed. Rows are keyed on the file
+// path and on the group size and hash, so scanning again updates the rows
+// in place. Files that were never hashed, since no other file had the same
+// size, have a NULL hash.
+#[cfg(feature = "sqlite")]
+pub fn write_catalog(dups: &AllInFileVisitor, db: &Path) -> io::Result<()> {
+    write_catalog_sqlite(dups, db).map_err(|e| io::Error::other(e.to_string()))
+}
+
+#[cfg(feature = "sqlite")]
+fn write_catalog_sqlite(dups: &AllInFileVisitor, db: &Path) -> rusqlite::Result<()> {
+    use crate::lsdup::devino::DevIno;
+    use rusqlite::params;
+
+    let mut conn = rusqlite::Connection::open(db)?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS files (
+             path TEXT PRIMARY KEY,
+             size INTEGER NOT NULL,
+             hash TEXT,
+             dev INTEGER,
+             ino INTEGER
+         );
+         CREATE TABLE IF NOT EXISTS groups (
+             hash TEXT NOT NULL,
+             size INTEGER NOT NULL,
+             count INTEGER NOT NULL,
+             PRIMARY KEY (size, hash)
+         );",
+    )?;
+    {
+        let mut insert_file = tx.prepare(
+            "INSERT OR REPLACE INTO files (path, size, hash, dev, ino) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (lenhash, paths) in dups.all_groups() {
+            for path in paths {
+                let devino = DevIno::from_path(path);
+                insert_file.execute(params![
+                    path.to_string_lossy(),
+                    lenhash.len() as i64,
+                    Some(lenhash.to_hex().as_str()),
+                    devino.as_ref().map(|d| d.dev() as i64),
+                    devino.as_ref().map(|d| d.ino() as i64),
+                ])?;
+            }
+        }
+        for (size, path) in dups.unhashed_files() {
+            let devino = DevIno::from_path(path);
+            insert_file.execute(params![
+                path.to_string_lossy(),
+                size as i64,
+                None::<&str>,
+                devino.as_ref().map(|d| d.dev() as i64),
+                devino.as_ref().map(|d| d.ino() as i64),
+            ])?;
+        }
+
+        let mut insert_group =
+            tx.prepare("INSERT OR REPLACE INTO groups (hash, size, count) VALUES (?1, ?2, ?3)")?;
+        for (lenhash, paths) in dups {
+            insert_group.execute(params![
+                lenhash.to_hex().as_str(),
+                lenhash.len() as i64,
+                paths.len() as i64,
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_catalog(_dups: &AllInFileVisitor, db: &Path) -> io::Result<()> {
+    Err(io::Error::other(format!(
+        "{:?} can't be written, lsdup was built without sqlite support",
+        db
+    )))
+}
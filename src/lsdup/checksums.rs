@@ -0,0 +1,152 @@
+use crate::lsdup::hashing::HashAlgorithm;
+use crate::lsdup::lenhash::LenHash;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The files listed in a sha256sum or b3sum style manifest, given with
+// --match-manifest, by their hash. The manifest doesn't say which algorithm
+// made its hashes, so they are compared with the first --hash.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct Checksums {
+    files: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl Checksums {
+    // Number of files listed in the manifest.
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    // The files of the manifest with the same hash, if any.
+    pub fn matching(&self, lenhash: &LenHash) -> Option<&[PathBuf]> {
+        self.files.get(lenhash.to_hex().as_str()).map(Vec::as_slice)
+    }
+}
+
+// Reads the manifest from the file, of hashes made with the algorithm.
+pub fn load(file: &Path, algorithm: HashAlgorithm) -> io::Result<Checksums> {
+    let text = fs::read_to_string(file)?;
+    parse(&text, algorithm).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Reads "HASH  PATH" lines, as written by sha256sum and b3sum. A * before the
+// path marks it as read in binary mode, which makes no difference here. Lines
+// starting with a backslash have their path escaped, for paths with a
+// backslash or newline in them. Blank lines are left out. Each hash must be
+// as long as those of the algorithm, so that a manifest made with another
+// isn't quietly read as matching nothing.
+pub fn parse(text: &str, algorithm: HashAlgorithm) -> Result<Checksums, String> {
+    let hex_len = 2 * algorithm.digest_len();
+    let mut checksums = Checksums::default();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad_line = || format!("line {}: not a hash and path", number + 1);
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (hash, path) = line.split_once(' ').ok_or_else(bad_line)?;
+        if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(bad_line());
+        }
+        if hash.len() != hex_len {
+            return Err(format!(
+                "line {}: a hash of {} hex digits, but {} hashes have {}",
+                number + 1,
+                hash.len(),
+                algorithm.name(),
+                hex_len
+            ));
+        }
+        let path = path
+            .strip_prefix(' ')
+            .or_else(|| path.strip_prefix('*'))
+            .filter(|path| !path.is_empty())
+            .ok_or_else(bad_line)?;
+        let path = if escaped {
+            unescape(path).ok_or_else(bad_line)?
+        } else {
+            path.to_string()
+        };
+        checksums
+            .files
+            .entry(hash.to_ascii_lowercase())
+            .or_default()
+            .push(PathBuf::from(path));
+    }
+    Ok(checksums)
+}
+
+// Undoes the \\ and \n escapes of a path.
+fn unescape(path: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '\\' => unescaped.push('\\'),
+                'n' => unescaped.push('\n'),
+                _ => return None,
+            },
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = format!(
+            "{}  a.txt\n\
+             {} *dir/b c.txt\n\
+             \n\
+             \\{}  dir/d\\\\\\n.txt\n",
+            "ab".repeat(32),
+            "AB".repeat(32),
+            "cd".repeat(32)
+        );
+
+        let checksums = parse(&text, HashAlgorithm::Sha256).expect("Could not parse manifest.");
+
+        assert_eq!(3, checksums.len());
+        let ab = checksums
+            .matching(&LenHash::from(10, [0xab; 32]))
+            .expect("Expected the hash to be listed.");
+        assert_eq!(&[PathBuf::from("a.txt"), PathBuf::from("dir/b c.txt")], ab);
+        let cd = checksums
+            .matching(&LenHash::from(10, [0xcd; 32]))
+            .expect("Expected the hash to be listed.");
+        assert_eq!(&[PathBuf::from("dir/d\\\n.txt")], cd);
+        assert!(checksums.matching(&LenHash::from(10, [0xef; 32])).is_none());
+    }
+
+    #[test]
+    fn test_parse_bad() {
+        let parse = |text: &str| parse(text, HashAlgorithm::Sha256).map(|c| c.len());
+        assert!(parse("abcd  a.txt\n").is_err());
+        assert!(parse(&format!("{}\n", "ab".repeat(32))).is_err());
+        assert!(parse(&format!("{} a.txt\n", "ab".repeat(32))).is_err());
+        assert!(parse(&format!("\\{}  a\\x.txt\n", "ab".repeat(32))).is_err());
+        assert_eq!(
+            Err("line 2: not a hash and path".to_string()),
+            parse(&format!("{}  a.txt\nb.txt\n", "ab".repeat(32)))
+        );
+        // A longer hash, as sha512sum writes, isn't one of sha256.
+        assert_eq!(
+            Err("line 1: a hash of 128 hex digits, but sha256 hashes have 64".to_string()),
+            parse(&format!("{}  a.txt\n", "ab".repeat(64)))
+        );
+    }
+}
@@ -1,21 +1,315 @@
-use std::path::PathBuf;
-use clap::{App, Arg};
+use crate::lsdup::bytesize;
+use crate::lsdup::checksums::{self, Checksums};
+use crate::lsdup::configfile::{self, FileDefaults};
+use crate::lsdup::freespace;
+use crate::lsdup::hashing::{self, HashAlgorithm};
+use crate::lsdup::mtime;
+use crate::lsdup::pathlist;
+use crate::lsdup::prior::{self, PriorGroups, PriorScan};
+use clap::{App, Arg, ArgMatches};
+use glob::Pattern;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How the duplicate groups are written out.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum Format {
+    // Size and hash header, followed by one path per line, for each group.
+    Human,
+    // One JSON object per group, per line.
+    Ndjson,
+    // One path per line, with a blank line after each group, like fdupes.
+    Fdupes,
+    // Every file and group, written to an SQLite database.
+    Sqlite,
+    // Each path followed by a NUL, with another NUL after each group.
+    NullGroups,
+    // One line per group, with the size, count, hash, and original in
+    // aligned columns.
+    Table,
+}
+
+// Which file of each duplicate group is kept as the original.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum Keep {
+    // The file under the earliest of the given paths, then alphabetically.
+    First,
+    // The file with the oldest modification time.
+    Oldest,
+    // The file with the newest modification time.
+    Newest,
+    // The file with the shortest path.
+    ShortestPath,
+}
+
+// What to do with the duplicate files that were found.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum Action {
+    // Replace each duplicate with a copy-on-write clone of the original.
+    Reflink,
+    // Delete each duplicate. Only done with --interactive, which asks which
+    // file of each group to keep.
+    Delete,
+}
+
+// How paths are written in the ndjson, fdupes, and manifest output, for paths
+// that aren't valid Unicode.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum PathEncoding {
+    // As Unicode, with whatever isn't valid replaced by U+FFFD.
+    Lossy,
+    // As the bytes of the path. Not allowed for ndjson, since JSON must be
+    // Unicode.
+    Raw,
+    // As the base64 of the bytes of the path.
+    Base64,
+}
 
 #[derive(std::fmt::Debug)]
 pub struct Config {
+    // Directories to scan, and files to compare along with them.
     pub dirs: Vec<PathBuf>,
     pub verbosity: u8,
+    // Groups with fewer members than this are not listed as duplicates.
+    pub min_count: usize,
+    // Only files last modified within this window are compared.
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    // Only files with one of these lowercased extensions are compared. An
+    // empty string matches files without an extension.
+    pub extensions: Option<Vec<String>>,
+    // Print the device:inode of each file in a group.
+    pub show_inode: bool,
+    pub format: Format,
+    // Only print the summary, not the duplicate groups.
+    pub summary_only: bool,
+    // Don't print the summary, only the duplicate groups and any errors.
+    pub quiet: bool,
+    // Print canonical absolute paths instead of paths as they were found.
+    pub absolute: bool,
+    // Files of at least this many bytes are memory mapped to be hashed,
+    // smaller files are read.
+    pub mmap_threshold: u64,
+    // Size of the buffer used when reading files to hash them.
+    pub buffer_size: usize,
+    // Number of threads used to walk the directories. 1 walks them serially.
+    pub threads: usize,
+    // Which file of each group is the original, and is listed first.
+    pub keep: Keep,
+    // Compare the files within archives, as well as the archives themselves.
+    pub scan_archives: bool,
+    // What to do with the duplicates of each group, if anything.
+    pub action: Option<Action>,
+    // Only report what the action would do.
+    pub dry_run: bool,
+    // Ignore case when ordering the files of a group alphabetically.
+    pub ignore_case: bool,
+    // The least time between updates of the progress bar.
+    pub progress_interval: Duration,
+    // Only hash the first this many bytes of each file, so groups are
+    // likely but unverified duplicates.
+    pub quick: Option<u64>,
+    // Write the duplicate groups to this file instead of stdout.
+    pub output: Option<PathBuf>,
+    // Follow symlinks to directories and files, instead of skipping them.
+    pub follow_symlinks: bool,
+    // Only print this many groups, though all of them are in the summary.
+    pub limit: Option<usize>,
+    // Report how much space hardlinking the duplicates would save.
+    pub report_hardlink_savings: bool,
+    // Skip hidden files and directories.
+    pub skip_hidden: bool,
+    // Compare text files up to NORMALIZE_TEXT_MAX_SIZE as normalized text,
+    // ignoring line endings and trailing whitespace.
+    pub normalize_text: bool,
+    // Report the bytes wasted by duplicates of each file extension.
+    pub by_extension: bool,
+    // Walk each directory level by level, instead of one subdirectory at a
+    // time.
+    pub breadth_first: bool,
+    // Color the human output. Resolved from --color, so it is never set when
+    // the output isn't a terminal unless asked for.
+    pub color: bool,
+    // List hard links to the same data as duplicates of each other.
+    pub count_hardlinks: bool,
+    // Files larger than this many bytes are not compared.
+    pub max_size: Option<u64>,
+    // Hashes from an earlier ndjson result, reused for files that haven't
+    // changed since.
+    pub since: Option<PriorScan>,
+    // Only compare the files directly within each directory, not within its
+    // subdirectories.
+    pub no_recurse: bool,
+    // Count the files before comparing them, so the progress bar can show a
+    // percentage.
+    pub precount: bool,
+    // Mark the groups whose files don't all have the same name.
+    pub flag_renamed: bool,
+    // Mark the original of each group in the human output.
+    pub mark_original: bool,
+    // Number of files read and hashed at once. 1 hashes each file as it is
+    // found.
+    pub io_threads: usize,
+    // Hash every file, and list each with its hash instead of listing the
+    // groups of duplicates.
+    pub manifest: bool,
+    // Print paths relative to this canonical directory, where they are
+    // within it.
+    pub relative_to: Option<PathBuf>,
+    // Ask which file of each group to keep, and delete the others.
+    pub interactive: bool,
+    // Compare the first and last blocks of large files of the same size
+    // before hashing them whole, so files that differ there are never read
+    // whole. Off with --no-ends-check.
+    pub ends_check: bool,
+    // Line written between groups in the human format, instead of a blank
+    // line before each group.
+    pub group_separator: Option<String>,
+    // Leave out the size and hash line of each group in the human format,
+    // or the column headings of the table format.
+    pub no_header: bool,
+    // Only list the files of each group that have the same name, ignoring
+    // copy suffixes such as " (1)".
+    pub same_name: bool,
+    // Files and directories whose path or name matches any of these are
+    // left out, from --exclude and --exclude-from.
+    pub excludes: Vec<Pattern>,
+    // The algorithms files are hashed with. Files are only the same if every
+    // one of them matches.
+    pub hashes: Vec<HashAlgorithm>,
+    // Only compare the files of about this percent of the sizes, to estimate
+    // the duplication of the whole.
+    pub sample: Option<f64>,
+    // Picks which sizes are in the sample.
+    pub seed: u64,
+    // Files with a size within any of these inclusive ranges are skipped.
+    pub excluded_sizes: Vec<(u64, u64)>,
+    // The groups of an earlier ndjson result, to list which groups are still
+    // duplicated, newly duplicated, or resolved since.
+    pub groups_from: Option<PriorGroups>,
+    // Only list groups whose duplicates take up at least this many bytes.
+    // The totals still count every group.
+    pub min_waste: Option<u64>,
+    // Write a script of the action to this file, instead of taking it.
+    pub script: Option<PathBuf>,
+    // Only compare files of at least this many bytes. Empty files are left
+    // out unless this is 0.
+    pub min_size: u64,
+    // Only list the original of each group, not its duplicates.
+    pub originals_only: bool,
+    // Only walk and count the files, without hashing or grouping any, to
+    // time the walk on its own.
+    pub no_hash: bool,
+    // How paths are written in the machine-readable formats.
+    pub path_encoding: PathEncoding,
+    // Only count how many files there are of each size, without hashing any,
+    // and print how they are spread over sizes.
+    pub size_histogram: bool,
+    // Files and directories whose path within the directory being scanned
+    // matches any of these are left out, from the --exclude patterns that
+    // start with / or ./, which are kept here without it.
+    pub anchored_excludes: Vec<Pattern>,
+    // Choose whether to memory map files by how fast it turns out to be,
+    // instead of by mmap_threshold.
+    pub adaptive_io: bool,
+    // Treat paths that only differ by case as the same path while walking.
+    pub case_fold: bool,
+    // Print when each file was modified and its status changed.
+    pub show_times: bool,
+    // Stop at the first group of duplicates found, and only list it.
+    pub any: bool,
+    // The files of a sha256sum style manifest, to list the scanned files
+    // with the same contents as one of them.
+    pub match_manifest: Option<Checksums>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            dirs: vec![PathBuf::from(".")],
+            verbosity: 0,
+            min_count: 2,
+            modified_after: None,
+            modified_before: None,
+            extensions: None,
+            show_inode: false,
+            format: Format::Human,
+            summary_only: false,
+            quiet: false,
+            absolute: false,
+            mmap_threshold: hashing::DEFAULT_MMAP_THRESHOLD,
+            buffer_size: hashing::DEFAULT_BUFFER_SIZE,
+            threads: 1,
+            keep: Keep::First,
+            scan_archives: false,
+            action: None,
+            dry_run: false,
+            ignore_case: false,
+            progress_interval: Duration::from_millis(100),
+            quick: None,
+            output: None,
+            follow_symlinks: false,
+            limit: None,
+            report_hardlink_savings: false,
+            skip_hidden: false,
+            normalize_text: false,
+            by_extension: false,
+            breadth_first: false,
+            color: false,
+            count_hardlinks: false,
+            max_size: None,
+            since: None,
+            no_recurse: false,
+            precount: false,
+            flag_renamed: false,
+            mark_original: false,
+            io_threads: 1,
+            manifest: false,
+            relative_to: None,
+            interactive: false,
+            ends_check: true,
+            group_separator: None,
+            no_header: false,
+            same_name: false,
+            excludes: Vec::new(),
+            hashes: vec![HashAlgorithm::Blake3],
+            sample: None,
+            seed: 0,
+            excluded_sizes: Vec::new(),
+            groups_from: None,
+            min_waste: None,
+            script: None,
+            min_size: 1,
+            originals_only: false,
+            no_hash: false,
+            path_encoding: PathEncoding::Lossy,
+            size_histogram: false,
+            anchored_excludes: Vec::new(),
+            adaptive_io: false,
+            case_fold: false,
+            show_times: false,
+            any: false,
+            match_manifest: None,
+        }
+    }
 }
 
 impl Config {
-    pub fn new() -> Result<Config, &'static str> {
-        let matches = App::new("List Duplicates")
+    pub fn new() -> Result<Config, String> {
+        let app = App::new("List Duplicates")
             .version("0.1.0")
             .author("redsaz <redsaz@gmail.com>")
             .about("Finds files with duplicate contents")
+            .after_help(
+                "Exit status is 0 if no duplicates were found, 1 if duplicates were found, \
+                 and 2 if an error occurred.",
+            )
             .arg(
                 Arg::with_name("DIR")
-                    .help("The directory to scan")
+                    .help("The directories to scan, and files to compare along with them")
                     .multiple(true)
                     .last(true)
                     .default_value("."),
@@ -24,22 +318,1263 @@ impl Config {
                 Arg::with_name("verbose")
                     .short('v')
                     .long("verbose")
-                    .multiple(true)
-                    .help("Sets the level of verbosity, repeat for more verbosity"),
+                    .multiple_occurrences(true)
+                    .help(
+                        "Sets the level of verbosity, repeat for more verbosity. At -vvv, every \
+                         file and directory left out of the scan is listed with the reason why",
+                    ),
+            )
+            .arg(
+                Arg::with_name("min-count")
+                    .long("min-count")
+                    .takes_value(true)
+                    .value_name("N")
+                    .default_value("2")
+                    .help("Only list groups with at least N identical files"),
+            )
+            .arg(
+                Arg::with_name("modified-after")
+                    .long("modified-after")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .help(
+                        "Only compare files modified at or after TIME, given as a UTC date \
+                         (2023-01-15 or 2023-01-15T10:30:00Z) or an age (30m, 12h, 7d, 2w). \
+                         Files without a readable modification time are skipped",
+                    ),
+            )
+            .arg(
+                Arg::with_name("modified-before")
+                    .long("modified-before")
+                    .takes_value(true)
+                    .value_name("TIME")
+                    .help("Only compare files modified before TIME, in the same forms as --modified-after"),
+            )
+            .arg(
+                Arg::with_name("extensions")
+                    .long("extensions")
+                    .takes_value(true)
+                    .value_name("EXT,...")
+                    .help(
+                        "Only compare files with one of the comma-separated extensions, ignoring \
+                         case. Include an empty entry (as in \"txt,\") to also compare files \
+                         without an extension",
+                    ),
+            )
+            .arg(
+                Arg::with_name("show-inode")
+                    .long("show-inode")
+                    .help("Print the device:inode before each file, to see which are hard links"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .value_name("FORMAT")
+                    .possible_values(["human", "ndjson", "fdupes", "sqlite", "null-groups", "table"])
+                    .default_value("human")
+                    .help(
+                        "How to write the duplicate groups. ndjson writes one JSON object per \
+                         line, with the size, hash, and paths of the group, then a last line \
+                         with a \"summary\" object of the totals. fdupes writes one \
+                         path per line with a blank line after each group, as fdupes does. \
+                         sqlite writes every file and group to the database given by --output, \
+                         replacing rows from earlier scans of the same files. null-groups \
+                         writes each path followed by a NUL character, and another NUL after \
+                         each group, so groups can be read back whatever the file names are. \
+                         table writes one line per group, with the size, count, start of the \
+                         hash, and original in aligned columns, cutting paths to fit the \
+                         terminal. \
+                         Groups are always listed largest files first",
+                    ),
+            )
+            .arg(
+                Arg::with_name("summary-only")
+                    .long("summary-only")
+                    .help("Only print the summary of what was found, not the duplicate groups"),
+            )
+            .arg(
+                Arg::with_name("quiet")
+                    .short('q')
+                    .long("quiet")
+                    .help(
+                        "Don't print the summary of what was found to stderr, such as the totals \
+                         and how long the scan took, only the duplicate groups and any errors",
+                    ),
+            )
+            .arg(
+                Arg::with_name("absolute")
+                    .long("absolute")
+                    .help("Print canonical absolute paths, with symlinks in the directories resolved"),
+            )
+            .arg(
+                Arg::with_name("mmap-threshold")
+                    .long("mmap-threshold")
+                    .takes_value(true)
+                    .value_name("BYTES")
+                    .default_value("16384")
+                    .help(
+                        "Memory map files of at least this many bytes to hash them, instead of \
+                         reading them. 0 always maps, a very large value never maps",
+                    ),
+            )
+            .arg(
+                Arg::with_name("buffer-size")
+                    .long("buffer-size")
+                    .takes_value(true)
+                    .value_name("BYTES")
+                    .default_value("8192")
+                    .help(
+                        "Size of the buffer for reading files that aren't memory mapped. Larger \
+                         buffers can help on spinning disks and network filesystems",
+                    ),
+            )
+            .arg(
+                Arg::with_name("threads")
+                    .long("threads")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help(
+                        "Number of threads to walk the directories with in parallel, or 0 for \
+                         one per core, the default. Only the walk is parallel: how many files \
+                         are read and hashed at once is set by --io-threads",
+                    ),
+            )
+            .arg(
+                Arg::with_name("keep")
+                    .long("keep")
+                    .takes_value(true)
+                    .value_name("POLICY")
+                    .possible_values(["first", "oldest", "newest", "shortest-path"])
+                    .default_value("first")
+                    .help(
+                        "Which file of each group is the original to keep, and is listed first: \
+                         the first one under the earliest given DIR, the oldest or newest by \
+                         modification time, or the one with the shortest path. Ties go to the \
+                         alphabetically first path, so the same files are always kept",
+                    ),
+            )
+            .arg(
+                Arg::with_name("scan-archives")
+                    .long("scan-archives")
+                    .help(
+                        "Also compare the files within zip, tar, and gzipped tar archives. They \
+                         are listed as the archive path and the path within it, joined by \"!\", \
+                         and are left out by --exclude, --no-hidden, and the modification times \
+                         the same as files are",
+                    ),
+            )
+            .arg(
+                Arg::with_name("reflink")
+                    .long("reflink")
+                    .help(
+                        "Replace each duplicate with a copy-on-write clone of the original, so \
+                         they share data on disk but stay independent files. Needs a filesystem \
+                         with reflink support, such as btrfs or XFS, on Linux",
+                    ),
+            )
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Only print what an action would do, without changing any files"),
+            )
+            .arg(
+                Arg::with_name("ignore-case")
+                    .long("ignore-case")
+                    .help(
+                        "Ignore case when ordering the files of each group alphabetically. This \
+                         only changes which file is listed first as the original, not which \
+                         files are duplicates",
+                    ),
+            )
+            .arg(
+                Arg::with_name("progress-interval")
+                    .long("progress-interval")
+                    .takes_value(true)
+                    .value_name("MS")
+                    .default_value("100")
+                    .help("Least number of milliseconds between updates of the progress display"),
+            )
+            .arg(
+                Arg::with_name("quick")
+                    .long("quick")
+                    .alias("first-bytes")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help(
+                        "Only compare the size and the first N bytes of files, which is much \
+                         faster for large files but can group files that differ after that. \
+                         These groups are marked as unverified, and no action can be taken on them",
+                    ),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .long("output")
+                    .short('o')
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Write the duplicate groups to FILE instead of stdout. Progress and the \
+                         summary are still written to stderr",
+                    ),
+            )
+            .arg(
+                Arg::with_name("follow-symlinks")
+                    .long("follow-symlinks")
+                    .help(
+                        "Follow symlinks to directories and files. Directories reached more than \
+                         once, such as through a symlink to a parent, are only read the first \
+                         time, and a file reached more than once is only counted once. A DIR \
+                         that is itself a symlink is always followed",
+                    ),
+            )
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help(
+                        "Only print the first N groups, largest files first. The summary still \
+                         counts all of them",
+                    ),
+            )
+            .arg(
+                Arg::with_name("report-hardlink-savings")
+                    .long("report-hardlink-savings")
+                    .help(
+                        "Report how much space hardlinking the duplicates would save, beyond what \
+                         existing hardlinks already share, without changing any files. With -v, \
+                         report it for each group",
+                    ),
+            )
+            .arg(
+                Arg::with_name("no-hidden")
+                    .long("no-hidden")
+                    .help(
+                        "Skip hidden files and directories: those with names starting with a dot, \
+                         or on Windows, with the hidden attribute",
+                    ),
+            )
+            .arg(
+                Arg::with_name("paths-from-null")
+                    .long("paths-from-null")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Also compare the files and directories listed in FILE, or stdin if FILE \
+                         is -, separated by NUL characters as written by find -print0. Unlike \
+                         newlines, NUL can't be part of a path",
+                    ),
+            )
+            .arg(
+                Arg::with_name("normalize-text")
+                    .long("normalize-text")
+                    .help(
+                        "Compare text files of up to 1 MiB ignoring line endings (CRLF or LF), \
+                         trailing whitespace on each line, and blank lines at the end. Files \
+                         grouped this way are not byte for byte identical, and their size is \
+                         that of the normalized text. Files with NUL bytes are treated as \
+                         binary and compared as they are",
+                    ),
+            )
+            .arg(
+                Arg::with_name("by-extension")
+                    .long("by-extension")
+                    .help("After the summary, report how many bytes duplicates of each file extension waste"),
+            )
+            .arg(
+                Arg::with_name("breadth-first")
+                    .long("breadth-first")
+                    .help(
+                        "Walk the directories level by level, so shallower files are scanned \
+                         before deeper ones. The same files are found either way, and groups \
+                         are still ordered by --keep",
+                    ),
+            )
+            .arg(
+                Arg::with_name("color")
+                    .long("color")
+                    .takes_value(true)
+                    .value_name("WHEN")
+                    .possible_values(["auto", "always", "never"])
+                    .default_value("auto")
+                    .help(
+                        "Whether to color the human output: auto only colors it when writing to a \
+                         terminal, not to a pipe or --output file",
+                    ),
+            )
+            .arg(
+                Arg::with_name("count-hardlinks")
+                    .long("count-hardlinks")
+                    .help(
+                        "List hard links to the same data as duplicates, instead of only the first \
+                         link found. Groups made only of links to the same data are still not \
+                         listed, since no space would be saved",
+                    ),
+            )
+            .arg(
+                Arg::with_name("max-size")
+                    .long("max-size")
+                    .takes_value(true)
+                    .value_name("BYTES")
+                    .help(
+                        "Only compare files of at most BYTES, such as 500MB or 2GiB. \"free\" uses the free space on the \
+                         filesystem of the given DIR with the least free, since larger files \
+                         couldn't be copied there anyway. Finding free space is only supported on \
+                         Unix-like platforms",
+                    ),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Reuse the hashes in FILE, the --format ndjson output of an earlier scan \
+                         with the same options, for files whose size and modification time haven't \
+                         changed since. Only files that were in a group then are listed there",
+                    ),
+            )
+            .arg(
+                Arg::with_name("no-recurse")
+                    .long("no-recurse")
+                    .help(
+                        "Only compare the files directly within each given DIR, without \
+                         descending into subdirectories",
+                    ),
+            )
+            .arg(
+                Arg::with_name("precount")
+                    .long("precount")
+                    .help(
+                        "Count the files first, so progress can be shown as a percentage of the \
+                         files found. This walks the directories twice",
+                    ),
+            )
+            .arg(
+                Arg::with_name("flag-renamed")
+                    .long("flag-renamed")
+                    .help(
+                        "Mark groups whose files don't all have the same name, which are likely \
+                         renamed copies rather than the same file copied to another place. Only \
+                         the human and ndjson formats are marked",
+                    ),
+            )
+            .arg(
+                Arg::with_name("mark-original")
+                    .long("mark-original")
+                    .help(
+                        "Mark the original of each group with \"* \" before its path, and indent \
+                         its duplicates to line up. Only the human format is marked",
+                    ),
+            )
+            .arg(
+                Arg::with_name("io-threads")
+                    .long("io-threads")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help(
+                        "Number of files to read and hash at once, or 0 for one per core. \
+                         Defaults to --threads, and so to one per core when neither is given. \
+                         On a single spinning disk, 1 avoids seeking back and forth between \
+                         files, while SSDs, disk arrays, and network filesystems can usually \
+                         keep several reads going. With more than 1, files are hashed once the \
+                         walk is done",
+                    ),
+            )
+            .arg(
+                Arg::with_name("manifest")
+                    .long("manifest")
+                    .help(
+                        "Hash every file, even those no other file has the same size as, and \
+                         print \"HASH SIZE PATH\" for each, ordered by path, instead of the \
+                         groups of duplicates",
+                    ),
+            )
+            .arg(
+                Arg::with_name("relative-to")
+                    .long("relative-to")
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .help(
+                        "Print paths relative to DIR, in every format. Paths that aren't within \
+                         DIR are printed as canonical absolute paths instead, with a note",
+                    ),
+            )
+            .arg(
+                Arg::with_name("interactive")
+                    .long("interactive")
+                    .help(
+                        "For each group, list its files numbered and ask which one to keep, then \
+                         delete the others. Groups can be kept whole, or the rest skipped by \
+                         quitting. With --dry-run, only print what would be deleted. Needs \
+                         stdin and stdout to be a terminal",
+                    ),
+            )
+            .arg(
+                Arg::with_name("glob")
+                    .long("glob")
+                    .help(
+                        "Expand each DIR as a glob pattern, such as 'photos/2023-*', for when the \
+                         shell doesn't. All matching directories and files are compared, and \
+                         patterns that match nothing are an error. Off by default, so names \
+                         with brackets or * are taken as they are",
+                    ),
+            )
+            .arg(
+                Arg::with_name("no-ends-check")
+                    .long("no-ends-check")
+                    .help(
+                        "Always hash files of the same size whole. Otherwise, large files are \
+                         first compared by their first and last 64 KiB, and only files that \
+                         match there too are hashed whole",
+                    ),
+            )
+            .arg(
+                Arg::with_name("group-separator")
+                    .long("group-separator")
+                    .takes_value(true)
+                    .value_name("STR")
+                    .help(
+                        "Write STR on a line between groups, such as ---, instead of a blank \
+                         line before each group. Only the human format is separated this way",
+                    ),
+            )
+            .arg(
+                Arg::with_name("no-header")
+                    .long("no-header")
+                    .help(
+                        "Leave out the size and hash line before each group, so only the paths \
+                         of each group are listed. Only the human format has the line. For \
+                         the table format, leaves out the column headings",
+                    ),
+            )
+            .arg(
+                Arg::with_name("same-name")
+                    .long("same-name")
+                    .help(
+                        "Only list the files of each group that also have the same name, as \
+                         sets of their own. A \" (N)\" copy suffix before the extension, as in \
+                         \"report (1).pdf\", is ignored, so it has the same name as \
+                         \"report.pdf\". The other totals still count whole groups",
+                    ),
+            )
+            .arg(
+                Arg::with_name("exclude")
+                    .long("exclude")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .value_name("PATTERN")
+                    .help(
+                        "Leave out files and directories whose path or name matches the glob \
+                         pattern, such as '*.tmp' or 'node_modules'. A pattern starting with / or \
+                         ./, such as '/build', is anchored, as in .gitignore: it only matches the \
+                         path within the DIR being scanned, so ./build is left out but not \
+                         ./src/build, and its * doesn't match a /. Can be given more than once",
+                    ),
+            )
+            .arg(
+                Arg::with_name("exclude-from")
+                    .long("exclude-from")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Also leave out what matches the glob patterns in FILE, one per line. \
+                         Blank lines and lines starting with # are ignored",
+                    ),
             )
-            .get_matches();
+            .arg(
+                Arg::with_name("hash")
+                    .long("hash")
+                    .takes_value(true)
+                    .value_name("ALGORITHMS")
+                    .default_value("blake3")
+                    .help(
+                        "The hash algorithms to compare files with: blake3, sha256, or both \
+                         separated by a comma, such as blake3,sha256. With both, files are only \
+                         grouped if both hashes match, and both are listed for each group",
+                    ),
+            )
+            .arg(
+                Arg::with_name("sample")
+                    .long("sample")
+                    .takes_value(true)
+                    .value_name("PERCENT")
+                    .help(
+                        "Only compare the files of about PERCENT of the file sizes, picked at \
+                         random, and estimate the totals for all files from them. All files of \
+                         a picked size are compared, so the duplicates among them are all found",
+                    ),
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help("Picks the sizes for --sample, the same ones each time. Defaults to 0"),
+            )
+            .arg(
+                Arg::with_name("exclude-size-range")
+                    .long("exclude-size-range")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .value_name("MIN:MAX")
+                    .help(
+                        "Leave out files with a size from MIN to MAX, inclusive, such as \
+                         4KiB:8KiB for thumbnails. Either end can be left out. Can be given more \
+                         than once",
+                    ),
+            )
+            .arg(
+                Arg::with_name("groups-from")
+                    .long("groups-from")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Compare with the groups of an earlier --format ndjson result, and print \
+                         the groups still duplicated, newly duplicated, and resolved since, \
+                         instead of every group. Groups are the same if their contents are, so \
+                         use the same --hash for both",
+                    ),
+            )
+            .arg(
+                Arg::with_name("min-waste")
+                    .long("min-waste")
+                    .takes_value(true)
+                    .value_name("SIZE")
+                    .help(
+                        "Only list groups whose duplicates take up at least SIZE, such as 10MB, \
+                         counting every copy but the original. The totals still include every \
+                         group",
+                    ),
+            )
+            .arg(
+                Arg::with_name("script")
+                    .long("script")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Write a script to FILE that deletes the duplicates of each group, \
+                         keeping the original, instead of changing any files. With --reflink, \
+                         the script clones the original over each duplicate instead. The script \
+                         is for sh, or PowerShell on Windows, so it can be reviewed and then run",
+                    ),
+            )
+            .arg(
+                Arg::with_name("min-size")
+                    .long("min-size")
+                    .takes_value(true)
+                    .value_name("SIZE")
+                    .default_value("1")
+                    .help(
+                        "Only compare files of at least SIZE, such as 4KiB. The default of 1 \
+                         leaves out empty files, which are all identical to each other. Use 0 \
+                         to include them",
+                    ),
+            )
+            .arg(
+                Arg::with_name("originals-only")
+                    .long("originals-only")
+                    .help(
+                        "Only list the original of each group, the file that --keep keeps, \
+                         rather than all of its files. The fdupes format then has one path per \
+                         line, and null-groups one path per NUL, with nothing between groups",
+                    ),
+            )
+            .arg(
+                Arg::with_name("no-hash")
+                    .long("no-hash")
+                    .help(
+                        "Only walk the directories and count the files and bytes, without \
+                         hashing or comparing any, and print the summary. Useful to time the walk \
+                         apart from the hashing",
+                    ),
+            )
+            .arg(
+                Arg::with_name("path-encoding")
+                    .long("path-encoding")
+                    .takes_value(true)
+                    .value_name("ENCODING")
+                    .possible_values(["lossy", "raw", "base64"])
+                    .default_value("lossy")
+                    .help(
+                        "How paths are written in the ndjson, fdupes, and --manifest output. \
+                         lossy replaces whatever isn't valid Unicode, which on Unix paths can \
+                         have, so those paths can't be read back. raw writes the bytes of each \
+                         path as they are, for fdupes and --manifest. base64 writes the base64 \
+                         of those bytes, and ndjson groups then have \"path_encoding\":\"base64\"",
+                    ),
+            )
+            .arg(
+                Arg::with_name("size-histogram")
+                    .long("size-histogram")
+                    .help(
+                        "Without hashing any files, print how many files there are in each range \
+                         of sizes, from one power of two up to the next, and how many of them \
+                         share their size with another file. Only those would be hashed by a \
+                         full scan, so this estimates how much reading it would do",
+                    ),
+            )
+            .arg(
+                Arg::with_name("adaptive-io")
+                    .long("adaptive-io")
+                    .help(
+                        "Instead of memory mapping files by --mmap-threshold, time the first \
+                         files read both ways, memory mapped and through the buffer, and read \
+                         the rest whichever way was faster. With -v, tells which was chosen",
+                    ),
+            )
+            .arg(
+                Arg::with_name("case-fold")
+                    .long("case-fold")
+                    .help(
+                        "Treat paths that only differ by case as the same path, as on the case \
+                         insensitive filesystems macOS and Windows use by default, so that a \
+                         directory or file reached by two spellings, such as Photos and photos, \
+                         is only scanned once. This only changes which paths are walked, not \
+                         which files have the same contents",
+                    ),
+            )
+            .arg(
+                Arg::with_name("show-times")
+                    .long("show-times")
+                    .help(
+                        "Also print when each file was last modified, and when its status last \
+                         changed (its ctime, on Unix only), for audits, as read when it was \
+                         scanned. The human format has them in UTC after each path. ndjson, which always has \"modified\", \
+                         adds \"changed\" in nanoseconds since the Unix epoch",
+                    ),
+            )
+            .arg(
+                Arg::with_name("any")
+                    .long("any")
+                    .help(
+                        "Stop scanning as soon as a group of duplicates is found, and only list \
+                         that group. The exit status still tells whether one was found, so this \
+                         is a quick check for any duplicates at all. Files are read one at a \
+                         time then, whatever --io-threads is",
+                    ),
+            )
+            .arg(
+                Arg::with_name("match-manifest")
+                    .long("match-manifest")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Hash every file, and print those with the same contents as a file \
+                         listed in a sha256sum or b3sum style manifest of \"HASH  PATH\" lines, \
+                         instead of the groups of duplicates. The manifest doesn't say how it was \
+                         hashed, so its hashes are compared with the first --hash: give --hash \
+                         sha256 for a sha256sum manifest",
+                    ),
+            )
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .help(
+                        "Read defaults for the options from the TOML file, with a key for each \
+                         option, such as threads = 4 or no-hidden = true. Options given here \
+                         take precedence. Without this, ~/.config/lsdup/config.toml is read if \
+                         it exists",
+                    ),
+            );
+
+        // Every option but --config and those that change files can be set in
+        // the config file, by its long name, and whether it takes a value.
+        let settable: BTreeMap<String, bool> = app
+            .get_arguments()
+            .filter(|arg| {
+                arg.get_long()
+                    .is_some_and(|long| !configfile::COMMAND_LINE_ONLY.contains(&long))
+            })
+            .filter_map(|arg| Some((arg.get_long()?.to_string(), arg.is_takes_value_set())))
+            .collect();
+        let cli = app.get_matches();
+        let file_defaults = match cli.value_of("config") {
+            Some(file) => configfile::load(Path::new(file), &settable)
+                .map_err(|e| format!("--config {}: {}", file, e))?,
+            None => match configfile::default_path() {
+                Some(file) if file.exists() => configfile::load(&file, &settable)
+                    .map_err(|e| format!("{}: {}", file.display(), e))?,
+                _ => FileDefaults::default(),
+            },
+        };
+        let matches = Options {
+            cli: &cli,
+            file: file_defaults,
+        };
 
-        let val_strings = matches
+        let val_strings = cli
             .get_many::<String>("DIR")
             .map(|vals| vals.collect::<Vec<_>>())
             .unwrap_or_default();
-        let dirs = val_strings
+        let mut dirs: Vec<PathBuf> = if matches.is_present("glob") {
+            let patterns: Vec<&str> = val_strings.iter().map(|s| s.as_str()).collect();
+            pathlist::expand_globs(&patterns).map_err(|e| format!("--glob: {}", e))?
+        } else {
+            val_strings.into_iter().map(PathBuf::from).collect()
+        };
+        if let Some(list) = matches.value_of("paths-from-null") {
+            let paths = pathlist::read_null_separated(Path::new(list))
+                .map_err(|e| format!("--paths-from-null {}: {}", list, e))?;
+            // Only scan the default directory if no paths were listed at all.
+            if matches.occurrences_of("DIR") == 0 {
+                dirs.clear();
+            }
+            dirs.extend(paths);
+        }
+
+        let verbosity = matches.occurrences_of("verbose") as u8;
+
+        let min_count = matches
+            .value_of("min-count")
+            .unwrap_or("2")
+            .parse::<usize>()
+            .map_err(|_| "--min-count must be a whole number".to_string())?;
+        if min_count < 2 {
+            return Err("--min-count must be 2 or more".to_string());
+        }
+
+        let now = SystemTime::now();
+        let modified_after = match matches.value_of("modified-after") {
+            Some(value) => Some(
+                mtime::parse(value, now)
+                    .map_err(|e| format!("--modified-after {}: {}", value, e))?,
+            ),
+            None => None,
+        };
+        let modified_before = match matches.value_of("modified-before") {
+            Some(value) => Some(
+                mtime::parse(value, now)
+                    .map_err(|e| format!("--modified-before {}: {}", value, e))?,
+            ),
+            None => None,
+        };
+
+        let extensions = matches.value_of("extensions").map(|value| {
+            value
+                .split(',')
+                .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                .collect()
+        });
+
+        let show_inode = matches.is_present("show-inode");
+
+        let format = match matches.value_of("format") {
+            Some("ndjson") => Format::Ndjson,
+            Some("fdupes") => Format::Fdupes,
+            Some("sqlite") => Format::Sqlite,
+            Some("null-groups") => Format::NullGroups,
+            Some("table") => Format::Table,
+            _ => Format::Human,
+        };
+
+        let summary_only = matches.is_present("summary-only");
+        let quiet = matches.is_present("quiet");
+        if matches.given("quiet") && matches.given("summary-only") {
+            return Err(
+                "--quiet can't be used with --summary-only, there would be nothing to print"
+                    .to_string(),
+            );
+        }
+        if matches.given("quiet") && matches.given("verbose") {
+            return Err("--quiet can't be used with --verbose".to_string());
+        }
+
+        let absolute = matches.is_present("absolute");
+
+        let mmap_threshold = matches
+            .value_of("mmap-threshold")
+            .unwrap_or("16384")
+            .parse::<u64>()
+            .map_err(|_| "--mmap-threshold must be a whole number of bytes".to_string())?;
+
+        let buffer_size = matches
+            .value_of("buffer-size")
+            .unwrap_or("8192")
+            .parse::<usize>()
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| "--buffer-size must be a positive whole number of bytes".to_string())?;
+
+        // Both default to one thread per core, or 1 if that can't be told, as
+        // does 0.
+        let threads = match matches.value_of("threads").unwrap_or("0").parse::<usize>() {
+            Ok(0) => cores(),
+            Ok(threads) => threads,
+            Err(_) => return Err("--threads must be a whole number".to_string()),
+        };
+
+        let io_threads = match matches.value_of("io-threads").map(str::parse::<usize>) {
+            Some(Ok(0)) => cores(),
+            Some(Ok(io_threads)) => io_threads,
+            Some(Err(_)) => return Err("--io-threads must be a whole number".to_string()),
+            None => threads,
+        };
+
+        let keep = match matches.value_of("keep") {
+            Some("oldest") => Keep::Oldest,
+            Some("newest") => Keep::Newest,
+            Some("shortest-path") => Keep::ShortestPath,
+            _ => Keep::First,
+        };
+
+        let scan_archives = matches.is_present("scan-archives");
+
+        let interactive = matches.is_present("interactive");
+        let action = match (matches.is_present("reflink"), interactive) {
+            (true, true) => return Err("--interactive can't be used with --reflink".to_string()),
+            (true, false) => Some(Action::Reflink),
+            (false, true) => Some(Action::Delete),
+            (false, false) => None,
+        };
+        if interactive && !(io::stdin().is_terminal() && io::stdout().is_terminal()) {
+            return Err("--interactive needs stdin and stdout to be a terminal".to_string());
+        }
+        let dry_run = matches.is_present("dry-run");
+
+        let script = matches.value_of("script").map(PathBuf::from);
+        if script.is_some() && interactive {
+            return Err(
+                "--script can't be used with --interactive, it can't ask which file to keep"
+                    .to_string(),
+            );
+        }
+        if script.is_some() && action == Some(Action::Reflink) && cfg!(target_family = "windows") {
+            return Err("--script can't be used with --reflink on Windows".to_string());
+        }
+        // Whether duplicates are changed, or a script is written to change them,
+        // which needs them to be identical just the same.
+        let acts = action.is_some() || script.is_some();
+
+        let ignore_case = matches.is_present("ignore-case");
+
+        let progress_interval = matches
+            .value_of("progress-interval")
+            .unwrap_or("100")
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .map_err(|_| {
+                "--progress-interval must be a whole number of milliseconds".to_string()
+            })?;
+
+        let quick =
+            matches
+                .value_of("quick")
+                .map(|value| {
+                    value.parse::<u64>().ok().filter(|n| *n > 0).ok_or_else(|| {
+                        "--quick must be a positive whole number of bytes".to_string()
+                    })
+                })
+                .transpose()?;
+        if quick.is_some() && acts {
+            return Err(
+                "--quick can't be used with an action or --script, its matches aren't verified"
+                    .to_string(),
+            );
+        }
+
+        let output = matches.value_of("output").map(PathBuf::from);
+
+        let follow_symlinks = matches.is_present("follow-symlinks");
+
+        let limit = matches
+            .value_of("limit")
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| "--limit must be a whole number".to_string())
+            })
+            .transpose()?;
+
+        let report_hardlink_savings = matches.is_present("report-hardlink-savings");
+
+        let skip_hidden = matches.is_present("no-hidden");
+
+        if format == Format::Sqlite && output.is_none() {
+            return Err("--format sqlite needs --output FILE for the database".to_string());
+        }
+
+        let normalize_text = matches.is_present("normalize-text");
+
+        if normalize_text && acts {
+            return Err(
+                "--normalize-text can't be used with an action or --script, its matches aren't \
+                 identical"
+                    .to_string(),
+            );
+        }
+
+        let by_extension = matches.is_present("by-extension");
+
+        let breadth_first = matches.is_present("breadth-first");
+
+        let color = match matches.value_of("color") {
+            Some("always") => true,
+            Some("never") => false,
+            _ => output.is_none() && console::Term::stdout().is_term(),
+        };
+
+        let count_hardlinks = matches.is_present("count-hardlinks");
+
+        let max_size = match matches.value_of("max-size") {
+            Some("free") => {
+                let mut free = u64::MAX;
+                for dir in &dirs {
+                    let available = freespace::available_bytes(dir)
+                        .map_err(|e| format!("--max-size free: {:?}: {}", dir, e))?;
+                    free = free.min(available);
+                }
+                Some(free)
+            }
+            Some(value) => Some(
+                bytesize::parse(value)
+                    .map_err(|e| format!("--max-size must be a size or free: {}", e))?,
+            ),
+            None => None,
+        };
+
+        let since = match matches.value_of("since") {
+            Some(file) => {
+                Some(prior::load(Path::new(file)).map_err(|e| format!("--since {}: {}", file, e))?)
+            }
+            None => None,
+        };
+        if since.is_some() && quick.is_some() {
+            return Err(
+                "--since can't be used with --quick, it only has whole file hashes".to_string(),
+            );
+        }
+
+        let no_recurse = matches.is_present("no-recurse");
+
+        let precount = matches.is_present("precount");
+
+        let flag_renamed = matches.is_present("flag-renamed");
+
+        let mark_original = matches.is_present("mark-original");
+
+        let manifest = matches.is_present("manifest");
+        if manifest && matches.given("format") {
+            return Err(
+                "--manifest can't be used with --format, it has its own format".to_string(),
+            );
+        }
+
+        let mut exclude_patterns: Vec<String> = matches
+            .values_of("exclude")
             .into_iter()
-            .map(|val| PathBuf::from(val))
+            .map(String::from)
             .collect();
+        if let Some(file) = matches.value_of("exclude-from") {
+            let patterns = pathlist::read_patterns(Path::new(file))
+                .map_err(|e| format!("--exclude-from {}: {}", file, e))?;
+            exclude_patterns.extend(patterns);
+        }
+        let mut excludes = Vec::new();
+        let mut anchored_excludes = Vec::new();
+        for pattern in &exclude_patterns {
+            let anchored = pattern
+                .strip_prefix("./")
+                .or_else(|| pattern.strip_prefix('/'));
+            let glob = Pattern::new(anchored.unwrap_or(pattern))
+                .map_err(|e| format!("--exclude {}: {}", pattern, e))?;
+            match anchored {
+                Some(_) => anchored_excludes.push(glob),
+                None => excludes.push(glob),
+            }
+        }
 
-        let verbosity = matches.occurrences_of("verbose") as u8;
+        let hashes = matches
+            .value_of("hash")
+            .unwrap_or("blake3")
+            .split(',')
+            .map(|name| HashAlgorithm::from_name(name.trim()))
+            .collect::<Option<Vec<_>>>()
+            .filter(|hashes| {
+                !hashes.is_empty() && hashes.len() <= 2 && !hashes[1..].contains(&hashes[0])
+            })
+            .ok_or_else(|| {
+                "--hash must be blake3, sha256, or both separated by a comma".to_string()
+            })?;
+        if since.is_some() && hashes != [HashAlgorithm::Blake3] {
+            return Err(
+                "--since can't be used with --hash, its hashes are only blake3".to_string(),
+            );
+        }
+
+        let sample = match matches.value_of("sample") {
+            Some(value) => match value.trim_end_matches('%').parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => Some(percent),
+                _ => return Err("--sample must be a percent greater than 0, up to 100".to_string()),
+            },
+            None => None,
+        };
+        if sample.is_some() && acts {
+            return Err(
+                "--sample can't be used with an action or --script, it leaves out most files"
+                    .to_string(),
+            );
+        }
+        let seed = matches
+            .value_of("seed")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| "--seed must be a whole number".to_string())?;
+
+        let same_name = matches.is_present("same-name");
+        if same_name && acts {
+            return Err(
+                "--same-name can't be used with an action or --script, it only narrows what is \
+                 listed"
+                    .to_string(),
+            );
+        }
+
+        let relative_to = match matches.value_of("relative-to") {
+            Some(dir) => Some(
+                std::fs::canonicalize(dir).map_err(|e| format!("--relative-to {}: {}", dir, e))?,
+            ),
+            None => None,
+        };
+        if relative_to.is_some() && absolute {
+            return Err("--relative-to can't be used with --absolute".to_string());
+        }
 
-        Ok(Config { dirs, verbosity })
+        let excluded_sizes = matches
+            .values_of("exclude-size-range")
+            .into_iter()
+            .map(|range| {
+                bytesize::parse_range(range)
+                    .map_err(|e| format!("--exclude-size-range {}: {}", range, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let groups_from = match matches.value_of("groups-from") {
+            Some(file) => Some(
+                prior::load_groups(Path::new(file))
+                    .map_err(|e| format!("--groups-from {}: {}", file, e))?,
+            ),
+            None => None,
+        };
+        if groups_from.is_some() && (manifest || matches.given("format")) {
+            return Err(
+                "--groups-from can't be used with --format or --manifest, it has its own format"
+                    .to_string(),
+            );
+        }
+        if groups_from.is_some() && quick.is_some() {
+            return Err(
+                "--groups-from can't be used with --quick, it compares whole file hashes"
+                    .to_string(),
+            );
+        }
+
+        let min_waste = matches
+            .value_of("min-waste")
+            .map(|value| bytesize::parse(value).map_err(|e| format!("--min-waste: {}", e)))
+            .transpose()?;
+
+        let min_size = bytesize::parse(matches.value_of("min-size").unwrap_or("1"))
+            .map_err(|e| format!("--min-size: {}", e))?;
+        if max_size.is_some_and(|max| min_size > max) {
+            return Err("--min-size can't be larger than --max-size".to_string());
+        }
+
+        let no_hash = matches.is_present("no-hash");
+        if no_hash && (acts || manifest) {
+            return Err(
+                "--no-hash can't be used with an action, --script, or --manifest, nothing is \
+                 hashed for them"
+                    .to_string(),
+            );
+        }
+
+        let path_encoding = match matches.value_of("path-encoding") {
+            Some("raw") => PathEncoding::Raw,
+            Some("base64") => PathEncoding::Base64,
+            _ => PathEncoding::Lossy,
+        };
+        if path_encoding == PathEncoding::Raw && format == Format::Ndjson {
+            return Err(
+                "--path-encoding raw can't be used with --format ndjson, JSON must be Unicode; \
+                 use base64 instead"
+                    .to_string(),
+            );
+        }
+
+        let size_histogram = matches.is_present("size-histogram");
+        if size_histogram && (acts || manifest || groups_from.is_some() || matches.given("format"))
+        {
+            return Err(
+                "--size-histogram can't be used with an action, --script, --manifest, \
+                 --groups-from, or --format, it has its own output"
+                    .to_string(),
+            );
+        }
+
+        let adaptive_io = matches.is_present("adaptive-io");
+        if adaptive_io && matches.given("mmap-threshold") {
+            return Err(
+                "--adaptive-io can't be used with --mmap-threshold, it chooses for itself"
+                    .to_string(),
+            );
+        }
+
+        let any = matches.is_present("any");
+        if any && (acts || manifest || groups_from.is_some() || no_hash || size_histogram) {
+            return Err(
+                "--any can't be used with an action, --script, --manifest, --groups-from, \
+                 --no-hash, or --size-histogram, which need every file"
+                    .to_string(),
+            );
+        }
+
+        let match_manifest = match matches.value_of("match-manifest") {
+            Some(file) => Some(
+                checksums::load(Path::new(file), hashes[0])
+                    .map_err(|e| format!("--match-manifest {}: {}", file, e))?,
+            ),
+            None => None,
+        };
+        if match_manifest.is_some()
+            && (manifest || groups_from.is_some() || size_histogram || matches.given("format"))
+        {
+            return Err(
+                "--match-manifest can't be used with --format, --manifest, --groups-from, or \
+                 --size-histogram, it has its own format"
+                    .to_string(),
+            );
+        }
+        if match_manifest.is_some()
+            && (quick.is_some() || normalize_text || no_hash || any || sample.is_some())
+        {
+            return Err(
+                "--match-manifest can't be used with --quick, --normalize-text, --no-hash, --any, \
+                 or --sample, it compares the whole file hashes of every file"
+                    .to_string(),
+            );
+        }
+
+        Ok(Config {
+            dirs,
+            verbosity,
+            min_count,
+            modified_after,
+            modified_before,
+            extensions,
+            show_inode,
+            format,
+            summary_only,
+            quiet,
+            absolute,
+            mmap_threshold,
+            buffer_size,
+            threads,
+            keep,
+            scan_archives,
+            action,
+            dry_run,
+            ignore_case,
+            progress_interval,
+            quick,
+            output,
+            follow_symlinks,
+            limit,
+            report_hardlink_savings,
+            skip_hidden,
+            normalize_text,
+            by_extension,
+            breadth_first,
+            color,
+            count_hardlinks,
+            max_size,
+            excluded_sizes,
+            groups_from,
+            min_waste,
+            script,
+            min_size,
+            originals_only: matches.is_present("originals-only"),
+            no_hash,
+            path_encoding,
+            size_histogram,
+            anchored_excludes,
+            adaptive_io,
+            case_fold: matches.is_present("case-fold"),
+            show_times: matches.is_present("show-times"),
+            any,
+            since,
+            no_recurse,
+            precount,
+            flag_renamed,
+            mark_original,
+            io_threads,
+            manifest,
+            relative_to,
+            interactive,
+            ends_check: !matches.is_present("no-ends-check"),
+            group_separator: matches.value_of("group-separator").map(String::from),
+            no_header: matches.is_present("no-header"),
+            same_name,
+            excludes,
+            hashes,
+            sample,
+            seed,
+            match_manifest,
+        })
+    }
+}
+
+// The options given on the command line, or for those that weren't, from the
+// config file, or otherwise their defaults.
+struct Options<'a> {
+    cli: &'a ArgMatches,
+    file: FileDefaults,
+}
+
+impl<'a> Options<'a> {
+    fn value_of(&self, name: &str) -> Option<&str> {
+        match self.file.get(name) {
+            Some(value) if self.cli.occurrences_of(name) == 0 => Some(value),
+            _ => self.cli.value_of(name),
+        }
+    }
+
+    // Every value given for an option that can be given more than once. In
+    // the config file, they are an array.
+    fn values_of(&self, name: &str) -> Vec<&str> {
+        match self.file.get_all(name) {
+            Some(values) if self.cli.occurrences_of(name) == 0 => {
+                values.iter().map(String::as_str).collect()
+            }
+            _ => self.cli.values_of(name).into_iter().flatten().collect(),
+        }
+    }
+
+    fn occurrences_of(&self, name: &str) -> u64 {
+        match (self.cli.occurrences_of(name), self.file.get(name)) {
+            (0, Some(value)) => value.parse().unwrap_or(1),
+            (occurrences, _) => occurrences,
+        }
     }
+
+    // True if the option was given on the command line. Options that can't
+    // be used together are only checked for these, so that a default from the
+    // config file, such as a format, doesn't stop an option given for one run.
+    fn given(&self, name: &str) -> bool {
+        self.cli.occurrences_of(name) > 0
+    }
+
+    fn is_present(&self, name: &str) -> bool {
+        self.cli.is_present(name) || self.file.get(name).is_some()
+    }
+}
+
+// Number of cores, or 1 if that can't be told.
+fn cores() -> usize {
+    thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1)
 }
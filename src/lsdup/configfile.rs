@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Defaults for the command line options, read from a TOML file such as:
+//
+//     threads = 4
+//     format = "fdupes"
+//     max-size = 1000000000
+//     extensions = ["jpg", "png"]
+//     no-hidden = true
+//
+// Each key is the long name of an option. Options given on the command line
+// take precedence over the file. Each value is kept as it would be written
+// on the command line, so it is checked the same way.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct FileDefaults {
+    values: BTreeMap<String, String>,
+    // Each value given for options that take one, as given, for the options
+    // that can be given more than once.
+    lists: BTreeMap<String, Vec<String>>,
+}
+
+// Options that change or delete files, which can only be given on the
+// command line, so that no run does so without asking for it.
+pub const COMMAND_LINE_ONLY: &[&str] = &["config", "reflink", "interactive", "script"];
+
+impl FileDefaults {
+    // The value of the option as it would be written on the command line, or
+    // for a flag, the number of times it is given. None if it isn't set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    // Each value of the option, one for each element of an array, as if the
+    // option were given once for each. None if it isn't set.
+    pub fn get_all(&self, name: &str) -> Option<&[String]> {
+        self.lists.get(name).map(Vec::as_slice)
+    }
+}
+
+// Where the config file is looked for when --config isn't given:
+// lsdup/config.toml within $XDG_CONFIG_HOME, or ~/.config if that isn't set,
+// or within %APPDATA% on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(target_family = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(dir.join("lsdup").join("config.toml"))
+}
+
+// Reads the defaults from the file. Options are the long name of each option
+// that can be set, and whether it takes a value or is a flag.
+pub fn load(file: &Path, options: &BTreeMap<String, bool>) -> io::Result<FileDefaults> {
+    let text = fs::read_to_string(file)?;
+    parse(&text, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Reads the defaults from the TOML text. Options that take a value can be
+// given a string, a number, or an array of them, which is joined with commas,
+// or for options that can be given more than once, is each value given.
+// Flags can be true or false, or a number of times, as for verbose.
+pub fn parse(text: &str, options: &BTreeMap<String, bool>) -> Result<FileDefaults, String> {
+    let table = text.parse::<toml::Table>().map_err(|e| e.to_string())?;
+    let mut defaults = FileDefaults::default();
+    for (key, value) in table {
+        if COMMAND_LINE_ONLY.contains(&key.as_str()) {
+            return Err(format!("{} can only be given on the command line", key));
+        }
+        let takes_value = *options
+            .get(&key)
+            .ok_or_else(|| format!("{} is not an option", key))?;
+        let value = if takes_value {
+            let values = match value {
+                toml::Value::Array(values) => values
+                    .iter()
+                    .map(|value| scalar(&key, value))
+                    .collect::<Result<Vec<_>, _>>()?,
+                value => vec![scalar(&key, &value)?],
+            };
+            let joined = values.join(",");
+            defaults.lists.insert(key.clone(), values);
+            joined
+        } else {
+            match value {
+                toml::Value::Boolean(true) => "1".to_string(),
+                // A count of 0, as for verbose, is the same as not given.
+                toml::Value::Boolean(false) | toml::Value::Integer(0) => continue,
+                toml::Value::Integer(n) if n > 0 => n.to_string(),
+                _ => return Err(format!("{} must be true or false", key)),
+            }
+        };
+        defaults.values.insert(key, value);
+    }
+    Ok(defaults)
+}
+
+// The string or number as it would be written on the command line.
+fn scalar(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        _ => Err(format!("{} must be a string or a number", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> BTreeMap<String, bool> {
+        vec![
+            ("threads", true),
+            ("format", true),
+            ("extensions", true),
+            ("no-hidden", false),
+            ("verbose", false),
+            ("dry-run", false),
+            ("exclude", true),
+        ]
+        .into_iter()
+        .map(|(name, takes_value)| (name.to_string(), takes_value))
+        .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "threads = 4\n\
+                    format = \"fdupes\"\n\
+                    extensions = [\"jpg\", \"png\"]\n\
+                    no-hidden = true\n\
+                    dry-run = false\n\
+                    verbose = 2\n";
+
+        let defaults = parse(text, &options()).expect("Could not parse config.");
+
+        assert_eq!(Some("4"), defaults.get("threads"));
+        assert_eq!(Some("fdupes"), defaults.get("format"));
+        assert_eq!(Some("jpg,png"), defaults.get("extensions"));
+        assert_eq!(Some("1"), defaults.get("no-hidden"));
+        assert_eq!(Some("2"), defaults.get("verbose"));
+        // Flags that are false are the same as not set.
+        assert_eq!(None, defaults.get("dry-run"));
+    }
+
+    #[test]
+    fn test_parse_flag_zero() {
+        let text = "no-hidden = 0
+                    verbose = 0
+";
+
+        let defaults = parse(text, &options()).expect("Could not parse config.");
+
+        // A flag given a count of 0 is not set, the same as false.
+        assert_eq!(None, defaults.get("no-hidden"));
+        assert_eq!(None, defaults.get("verbose"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse("thraeds = 4\n", &options()).unwrap_err();
+        assert_eq!("thraeds is not an option", err);
+        let err = parse("no-hidden = \"yes\"\n", &options()).unwrap_err();
+        assert_eq!("no-hidden must be true or false", err);
+        let err = parse("threads = true\n", &options()).unwrap_err();
+        assert_eq!("threads must be a string or a number", err);
+        assert!(parse("threads = \n", &options()).is_err());
+    }
+
+    #[test]
+    fn test_parse_lists() {
+        let text = "exclude = [\"a,b\", \"*.tmp\"]\n\
+                    format = \"fdupes\"\n";
+
+        let defaults = parse(text, &options()).expect("Could not parse config.");
+
+        // Each value is kept whole, even with a comma in it.
+        let exclude = defaults.get_all("exclude").expect("Expected exclude.");
+        assert_eq!(&["a,b".to_string(), "*.tmp".to_string()], exclude);
+        assert_eq!(
+            Some(&["fdupes".to_string()][..]),
+            defaults.get_all("format")
+        );
+    }
+
+    #[test]
+    fn test_parse_command_line_only() {
+        // Options that change files can't be set for every run.
+        for key in &["reflink", "interactive", "script"] {
+            let err = parse(&format!("{} = true\n", key), &options()).unwrap_err();
+            assert_eq!(
+                format!("{} can only be given on the command line", key),
+                err
+            );
+        }
+    }
+}
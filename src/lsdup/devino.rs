@@ -6,6 +6,8 @@
     std::cmp::Ord,
     std::cmp::PartialOrd,
     std::fmt::Debug,
+    std::clone::Clone,
+    std::marker::Copy,
 )]
 pub struct DevIno {
     dev: u64,
@@ -20,11 +22,37 @@ impl DevIno {
         DevIno { dev, ino }
     }
 
+    pub fn dev(&self) -> u64 {
+        self.dev
+    }
+
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    // Reads the device+inode for the path, without following symlinks. None if
+    // the path can't be read, or the platform doesn't have inode numbers.
+    #[cfg(target_family = "unix")]
+    pub fn from_path(path: &std::path::Path) -> Option<DevIno> {
+        path.symlink_metadata().ok().map(|meta| DevIno::from(&meta))
+    }
+
     #[cfg(target_family = "windows")]
-    pub fn from(meta: &fs::Metadata) -> DevIno {
+    pub fn from_path(_path: &std::path::Path) -> Option<DevIno> {
+        None
+    }
+
+    #[cfg(target_family = "windows")]
+    pub fn from(_meta: &std::fs::Metadata) -> DevIno {
         // Don't worry, I know this is horrible.
         let dev = 0;
         let ino = 0;
         DevIno { dev, ino }
     }
 }
+
+impl std::fmt::Display for DevIno {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.dev, self.ino)
+    }
+}
@@ -1,35 +1,90 @@
-use crate::lsdup::config::Config;
+use crate::lsdup::archive;
+use crate::lsdup::config::{Config, Keep};
 use crate::lsdup::devino::DevIno;
+use crate::lsdup::hashing::{
+    hash_contents_path_adaptive, hash_contents_path_using, hash_contents_reader, hash_ends_path,
+    hash_normalized_text, hash_prefix_path, hash_prefix_reader, AdaptiveIo, ENDS_BLOCK_LEN,
+    NORMALIZE_TEXT_MAX_SIZE,
+};
 use crate::lsdup::lenhash::LenHash;
-use std::path::PathBuf;
-use indicatif::ProgressBar;
-use std::collections::BTreeMap;
+use crate::lsdup::mtime::FileTimes;
+use crate::lsdup::output;
+use crate::lsdup::skip::{self, Skip, SpecialFiles};
 use console::Term;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::cmp::Reverse;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::Metadata;
 use std::io;
 use std::path::Path;
-use std::fs::File;
-use memmap::MmapOptions;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-// len, hash, and first file.
-#[derive(std::fmt::Debug)]
-struct LinkedFile {
-    len: u64,
-    hash: Option<[u8; 32]>,
-    first: Option<PathBuf>,
+// Is given each file found by a walk, one at a time, from the thread doing
+// the walk. Even when several threads walk, as with more than one
+// `threads`, the files are gathered and then visited in order from one
+// thread, so a visitor needs no synchronization of its own.
+pub trait FileVisitor {
+    fn visit(&mut self, file: PathBuf);
+
+    // True once the visitor needs no more files, so the walk can stop
+    // early, such as with --any once a duplicate was found.
+    fn done(&self) -> bool {
+        false
+    }
 }
 
-impl LinkedFile {
-    pub fn init(len: u64, first: PathBuf) -> LinkedFile {
-        let hash = None;
-        let first = Some(first);
-        LinkedFile { len, hash, first }
+// Is given each file found by `walk_shared`, from whichever of the walking
+// threads found it, so `visit` can be called from several threads at once.
+// Each file is visited once, as soon as it is found, in no particular order.
+// Any state kept by the visitor must be synchronized, such as with atomics,
+// or by wrapping a `FileVisitor` in a `Mutex`, which visits one file at a
+// time.
+pub trait SharedFileVisitor: Sync {
+    fn visit(&self, file: PathBuf);
+}
+
+impl<V: FileVisitor + Send> SharedFileVisitor for Mutex<V> {
+    fn visit(&self, file: PathBuf) {
+        // A visitor that panicked has still visited the files before.
+        let mut visitor = self.lock().unwrap_or_else(|e| e.into_inner());
+        visitor.visit(file);
     }
 }
 
-pub trait FileVisitor {
-    fn visit(&mut self, file: PathBuf);
+// Collects the files, to be visited later.
+impl FileVisitor for Vec<PathBuf> {
+    fn visit(&mut self, file: PathBuf) {
+        self.push(file);
+    }
 }
 
+// Finds the duplicates among the files it is given.
+//
+// `run` walks the configured directories and gives each file to a visitor,
+// but a visitor can also be driven directly, such as with paths from some
+// other index: construct it, visit each path, finish, order the groups, and
+// then iterate over a reference to it for each group of duplicates. To
+// see every hashed file, whether it has duplicates or not, use `all_groups`.
+//
+//    use lsdup::lsdup::config::Config;
+//    use lsdup::AllInFileVisitor;
+//    use std::path::PathBuf;
+//
+//    let config = Config::default();
+//    let mut visitor = AllInFileVisitor::new(&config);
+//    for path in vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")] {
+//        visitor.visit_path(path);
+//    }
+//    visitor.finish();
+//    visitor.order_groups();
+//    for (lenhash, paths) in &visitor {
+//        println!("{} bytes: {:?}", lenhash.len(), paths);
+//    }
 #[derive(std::fmt::Debug)]
 pub struct AllInFileVisitor<'a> {
     config: &'a Config,
@@ -40,15 +95,44 @@ pub struct AllInFileVisitor<'a> {
     // Any later files will get hashed.
     size_firstfile_map: BTreeMap<u64, Option<PathBuf>>,
 
+    // Large files of the same size are first compared by their first and
+    // last blocks. As with sizes, the first file with the given blocks waits
+    // here, and is only hashed whole once another file has the same blocks.
+    ends_firstfile_map: BTreeMap<LenHash, Option<PathBuf>>,
+
+    // Sizes of archive entries seen. Entries can only be hashed whole, so
+    // files of these sizes are too, rather than compared by their blocks.
+    archive_sizes: BTreeSet<u64>,
+
     // If there are two or more files of a given size found, then they
     // will be hashed and placed in this map.
     hash_files_map: BTreeMap<LenHash, Vec<PathBuf>>,
 
+    // How many files there are of each size. With --size-histogram nothing
+    // is hashed, so this is all that is kept of the files.
+    size_counts: BTreeMap<u64, u32>,
+
     // Files that are hardlinked are treated specially, because the user
     // usually (unless an option is set otherwise) doesn't want to consider
     // hardlinks as duplicate. Also we don't want to hash two or more times
-    // if we know its all pointing to the same data.
-    hardlinks_map: BTreeMap<DevIno, LinkedFile>,
+    // if we know its all pointing to the same data. Only whether the data
+    // was seen matters, and how long the path of the link standing for it is,
+    // so just the inodes and those lengths are kept, which on trees with many
+    // links takes far less memory than a path for each.
+    seen_inodes: HashMap<DevIno, usize>,
+
+    // Links found after the one first seen for their inode, with a shorter
+    // path. The shortest link stands for the data in the results, as with
+    // --keep shortest-path, so which one that is doesn't depend on the order
+    // of the walk.
+    shorter_links: HashMap<DevIno, PathBuf>,
+
+    // With --count-hardlinks, the inode of each file that has hard links.
+    path_inodes: BTreeMap<PathBuf, DevIno>,
+
+    // With --show-times, when each file was modified and changed, as read
+    // when it was visited, so the results show the times of what was hashed.
+    file_times: BTreeMap<PathBuf, FileTimes>,
 
     // Total bytes of all the files processed.
     total_file_bytes: u64,
@@ -56,24 +140,103 @@ pub struct AllInFileVisitor<'a> {
     // Total number of files processed.
     num_files: u32,
 
-    // Displays progress/stats if attached to a terminal
-    progress_bar: ProgressBar,
+    // The files processed from each of the configured directories, in the
+    // same order. Duplicates are only counted once the scan is done.
+    root_totals: Vec<RootTotals>,
+
+    // Bytes of the files skipped because they are hard links to a file that
+    // was already seen, and so already share its data.
+    linked_bytes: u64,
+
+    // Number of files that were hashed, because another file had the same size.
+    num_hashed: u32,
+
+    // Number of those whose hash was reused from the --since result, instead
+    // of being read again.
+    num_reused: u32,
+
+    // With more than one --io-threads, the files waiting to be hashed in
+    // `finish`, and their sizes.
+    pending: Vec<(PathBuf, u64)>,
+
+    // With --adaptive-io, chooses how the files are read.
+    adaptive_io: Option<AdaptiveIo>,
+
+    // Files that could not be read to be compared, and why.
+    unreadable: Vec<(PathBuf, io::Error)>,
+
+    // Directories that could not be read while walking, and why.
+    unreadable_dirs: Vec<(PathBuf, io::Error)>,
+
+    // Number of dangling symlinks skipped while walking the directories.
+    broken_symlinks: u32,
+    // Fifos, sockets, devices, and the like found by the walk, which are skipped.
+    special_files: SpecialFiles,
+
+    // Displays progress/stats if attached to a terminal. Only created once
+    // there is progress to show.
+    progress_bar: Option<ProgressBar>,
+
+    // With --precount, how many files will be visited, and how many have
+    // been so far, so progress can be shown as a percentage.
+    expected_files: Option<u64>,
+    num_visited: u64,
 
     // Allows printing if actually a terminal
     term: Term,
+
+    // When the scan started, and once finished, how long it took.
+    started: Instant,
+    elapsed: Duration,
+
+    // When the progress bar was last updated, so it isn't updated more often
+    // than the configured interval.
+    last_progress: Option<Instant>,
+
+    // Once set, files put aside for --io-threads are no longer hashed.
+    stop: Option<&'a AtomicBool>,
+
+    // With --any, set once a group of duplicates was found.
+    found_any: bool,
 }
 
 impl<'a> AllInFileVisitor<'a> {
-    pub fn new(config: &'a Config) -> AllInFileVisitor {
+    // Creates a visitor that compares files according to the config. Only
+    // the options about which files to compare and how are used, not the
+    // directories to walk.
+    pub fn new(config: &'a Config) -> AllInFileVisitor<'a> {
         AllInFileVisitor {
             config,
             size_firstfile_map: BTreeMap::new(),
+            ends_firstfile_map: BTreeMap::new(),
+            archive_sizes: BTreeSet::new(),
             hash_files_map: BTreeMap::new(),
-            hardlinks_map: BTreeMap::new(),
+            size_counts: BTreeMap::new(),
+            seen_inodes: HashMap::new(),
+            shorter_links: HashMap::new(),
+            path_inodes: BTreeMap::new(),
+            file_times: BTreeMap::new(),
             total_file_bytes: 0,
             num_files: 0,
-            progress_bar: ProgressBar::new_spinner(),
+            root_totals: vec![RootTotals::default(); config.dirs.len()],
+            linked_bytes: 0,
+            num_hashed: 0,
+            num_reused: 0,
+            pending: Vec::new(),
+            adaptive_io: config.adaptive_io.then(AdaptiveIo::new),
+            unreadable: Vec::new(),
+            unreadable_dirs: Vec::new(),
+            broken_symlinks: 0,
+            special_files: SpecialFiles::default(),
+            progress_bar: None,
+            expected_files: None,
+            num_visited: 0,
             term: console::Term::stderr(),
+            started: Instant::now(),
+            elapsed: Duration::from_secs(0),
+            last_progress: None,
+            stop: None,
+            found_any: false,
         }
     }
 
@@ -84,31 +247,580 @@ impl<'a> AllInFileVisitor<'a> {
     pub fn total_file_bytes(&self) -> u64 {
         self.total_file_bytes
     }
-}
 
-impl<'a> FileVisitor for AllInFileVisitor<'a> {
-    fn visit(&mut self, file: PathBuf) {
-        if self.term.features().is_attended() {
-            let width = self.term.size_checked().unwrap_or((25, 40)).1 as usize;
-            let msg = file.to_str().unwrap_or("<invalid utf8>");
-            if width > 4 && msg.len() >= width - 3 {
-                for i in (0..(width - 3)).rev() {
-                    if msg.is_char_boundary(i) {
-                        self.progress_bar.set_message(msg.get(..i).unwrap_or(""));
-                        break;
-                    }
+    pub fn linked_bytes(&self) -> u64 {
+        self.linked_bytes
+    }
+
+    // How long the scan took, from creating the visitor until `finish`.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn num_hashed(&self) -> u32 {
+        self.num_hashed
+    }
+
+    pub fn num_reused(&self) -> u32 {
+        self.num_reused
+    }
+
+    // Files that could not be read, and so are missing from the results.
+    pub fn unreadable(&self) -> &[(PathBuf, io::Error)] {
+        &self.unreadable
+    }
+
+    // Directories that could not be read while walking, so any files in
+    // them are missing from the results.
+    pub fn unreadable_dirs(&self) -> &[(PathBuf, io::Error)] {
+        &self.unreadable_dirs
+    }
+
+    pub fn add_unreadable_dirs(&mut self, dirs: Vec<(PathBuf, io::Error)>) {
+        self.unreadable_dirs.extend(dirs);
+    }
+
+    pub fn broken_symlinks(&self) -> u32 {
+        self.broken_symlinks
+    }
+
+    pub fn add_broken_symlinks(&mut self, count: u32) {
+        self.broken_symlinks += count;
+    }
+
+    pub fn special_files(&self) -> SpecialFiles {
+        self.special_files
+    }
+
+    pub fn add_special_files(&mut self, special_files: SpecialFiles) {
+        let total = &mut self.special_files;
+        total.fifos += special_files.fifos;
+        total.sockets += special_files.sockets;
+        total.devices += special_files.devices;
+        total.other += special_files.other;
+    }
+
+    // Stops hashing the files put aside for --io-threads once the flag is
+    // set, such as by a Ctrl-C handler, so `finish` returns early.
+    pub fn set_stop(&mut self, stop: &'a AtomicBool) {
+        self.stop = Some(stop);
+    }
+
+    // True if the scan was stopped before every file was compared, so the
+    // results are only of the files compared until then.
+    pub fn interrupted(&self) -> bool {
+        self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    // With --any, true if a group of duplicates was found, and so the scan
+    // stopped there.
+    pub fn found_any(&self) -> bool {
+        self.found_any
+    }
+
+    // Shows progress as a percentage of this many files, instead of only
+    // counting them, once visiting starts.
+    pub fn set_expected_files(&mut self, count: u64) {
+        self.expected_files = Some(count);
+    }
+
+    // Every group of files with the same length and hash, including files
+    // that turned out not to have any duplicates, ordered from the largest
+    // files to the smallest.
+    //
+    // Unlike iterating over a reference to the visitor, which only gives the
+    // groups of duplicates with at least --min-count files, no group is left
+    // out. Only files that were hashed are included, so files that no other
+    // file had the same size as, which are never read, aren't; see
+    // `unhashed_files` for those.
+    pub fn all_groups(&self) -> impl Iterator<Item = (&LenHash, &Vec<PathBuf>)> {
+        self.hash_files_map.iter()
+    }
+
+    // Every file that wasn't hashed, because no other file had the same size,
+    // or the same first and last blocks, with its size.
+    pub fn unhashed_files(&self) -> impl Iterator<Item = (u64, &PathBuf)> {
+        let ends = self
+            .ends_firstfile_map
+            .iter()
+            .filter_map(|(ends, first)| first.as_ref().map(|first| (ends.len(), first)));
+        self.size_firstfile_map
+            .iter()
+            .filter_map(|(size, first)| first.as_ref().map(|first| (*size, first)))
+            .chain(ends)
+    }
+
+    // With --adaptive-io, how the files were chosen to be read.
+    pub fn adaptive_io(&self) -> Option<&AdaptiveIo> {
+        self.adaptive_io.as_ref()
+    }
+
+    // How many files were found of each size, ordered by size, as shown by
+    // --size-histogram.
+    pub fn size_counts(&self) -> &BTreeMap<u64, u32> {
+        &self.size_counts
+    }
+
+    // Number of files that are duplicates of the first file of their group.
+    // With --count-hardlinks, links to the same inode are counted once, since
+    // they take no more space.
+    pub fn num_duplicate_files(&self) -> usize {
+        self.into_iter()
+            .map(|(_, paths)| distinct_inodes(paths, &self.path_inodes) - 1)
+            .sum()
+    }
+
+    // Bytes taken up by the duplicate files, which could be reclaimed.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.into_iter()
+            .map(|(lenhash, paths)| self.wasted(lenhash, paths))
+            .sum()
+    }
+
+    // Bytes taken up by the duplicates of the first of the files, which have
+    // the contents of the lenhash. With --count-hardlinks, links to the same
+    // inode take no more space, so they are counted once.
+    pub fn wasted(&self, lenhash: &LenHash, paths: &[PathBuf]) -> u64 {
+        lenhash.len() * (distinct_inodes(paths, &self.path_inodes) - 1) as u64
+    }
+
+    // The files, of a group or part of one, as they are given to the
+    // formatters.
+    pub fn group<'g>(&self, lenhash: &'g LenHash, paths: &'g [PathBuf]) -> Group<'g> {
+        let times = if self.config.show_times {
+            paths
+                .iter()
+                .map(|path| self.file_times.get(path).copied().unwrap_or_default())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Group {
+            lenhash,
+            paths,
+            wasted: self.wasted(lenhash, paths),
+            times,
+        }
+    }
+
+    // What each of the configured directories contributed to the scan, in
+    // the same order as they were given. Duplicates are counted under the
+    // directory they were found in, not the one their original is in.
+    pub fn root_totals(&self) -> Vec<RootTotals> {
+        let mut totals = self.root_totals.clone();
+        for (_, paths) in self {
+            for path in &paths[1..] {
+                if let Some(root) = totals.get_mut(root_index(&self.config.dirs, path)) {
+                    root.duplicates += 1;
                 }
-            } else {
-                self.progress_bar.set_message(msg);
             }
         }
-        if let Err(e) = file.metadata() {
-            eprintln!("Error: Could not get metadata for {:?}: {}", file, e);
+        totals
+    }
+
+    // The group with the most files, or None if there are no groups. Of
+    // groups with as many files, the one of the largest files is returned.
+    pub fn largest_group(&self) -> Option<(&LenHash, &Vec<PathBuf>)> {
+        let mut largest: Option<(&LenHash, &Vec<PathBuf>)> = None;
+        for group in self {
+            if largest.is_none_or(|largest| group.1.len() > largest.1.len()) {
+                largest = Some(group);
+            }
+        }
+        largest
+    }
+
+    // Number of files that were never hashed, because no other file had the
+    // same size.
+    pub fn num_unique_sizes(&self) -> usize {
+        self.size_firstfile_map
+            .values()
+            .filter(|first| first.is_some())
+            .count()
+    }
+
+    // Number of files that were never hashed whole, because no other file
+    // had the same first and last blocks.
+    pub fn num_unique_ends(&self) -> usize {
+        self.ends_firstfile_map
+            .values()
+            .filter(|first| first.is_some())
+            .count()
+    }
+
+    // Compares the file with those visited before it. Files that can't be
+    // read are noted in `unreadable` rather than returned as errors.
+    pub fn visit_path(&mut self, file: PathBuf) {
+        // Whatever is still being walked isn't needed once done.
+        if self.found_any {
+            return;
+        }
+        self.num_visited += 1;
+        if self.term.features().is_attended() && self.progress_due() {
+            self.show_progress(&file);
+        }
+        let archive = if self.config.scan_archives && archive::is_archive(&file) {
+            Some(file.clone())
+        } else {
+            None
+        };
+        if self.extension_matches(&file) {
+            self.visit_file(file);
+        } else {
+            skip::log(self.config.verbosity, &file, Skip::Extension);
+        }
+        if let Some(archive) = archive {
+            self.visit_archive(&archive);
+        }
+    }
+
+    // Orders the files of each group so that the one to keep, according to
+    // the --keep policy, comes first. Files the policy can't tell apart are
+    // ordered alphabetically, ignoring case if --ignore-case was given, so the
+    // order never depends on the order the files happened to be visited in.
+    pub fn order_groups(&mut self) {
+        let keep = self.config.keep;
+        let ignore_case = self.config.ignore_case;
+        let roots = &self.config.dirs;
+        for paths in self.hash_files_map.values_mut() {
+            if paths.len() < 2 {
+                continue;
+            }
+            match keep {
+                Keep::First => paths.sort_by_cached_key(|path| {
+                    (root_index(roots, path), alphabetical_key(path, ignore_case))
+                }),
+                Keep::Oldest => paths.sort_by_cached_key(|path| {
+                    let modified = modified_time(path);
+                    (
+                        modified.is_none(),
+                        modified,
+                        alphabetical_key(path, ignore_case),
+                    )
+                }),
+                Keep::Newest => paths.sort_by_cached_key(|path| {
+                    let modified = modified_time(path);
+                    (
+                        modified.is_none(),
+                        Reverse(modified),
+                        alphabetical_key(path, ignore_case),
+                    )
+                }),
+                Keep::ShortestPath => paths.sort_by_cached_key(|path| {
+                    (path.as_os_str().len(), alphabetical_key(path, ignore_case))
+                }),
+            }
+        }
+    }
+
+    // Hashes any files put aside to be read by --io-threads, and clears the
+    // progress bar, so it doesn't linger above the results. Call once all
+    // the files have been visited.
+    pub fn finish(&mut self) {
+        self.hash_pending();
+        self.use_shorter_links();
+        self.elapsed = self.started.elapsed();
+        if let Some(progress_bar) = self.progress_bar.take() {
+            progress_bar.finish_and_clear();
+        }
+    }
+
+    // Puts the shortest link found to each file's data in place of the link
+    // that was hashed, which was the first one seen.
+    fn use_shorter_links(&mut self) {
+        if self.shorter_links.is_empty() {
             return;
         }
+        let shorter_links = std::mem::take(&mut self.shorter_links);
+        for path in self.hash_files_map.values_mut().flatten() {
+            if let Some(link) = DevIno::from_path(path).and_then(|inode| shorter_links.get(&inode))
+            {
+                *path = link.clone();
+            }
+        }
+    }
+
+    // Adds the results of another scan to these, as if its files had been
+    // visited here too, such as to combine scans of different directories
+    // or machines. Both should have been made with the same options, and
+    // finished. Files that only turn out to have the same size or first and
+    // last blocks as another once both scans are combined are hashed now,
+    // so they must be readable from here. The totals of each directory of the
+    // other scan are added to the same directory here, if there is one.
+    //
+    // A file found by both scans, by the same path or, if both saw its
+    // inode, by another hard link to it, is only counted once, as it was
+    // here. The groups are ordered again afterward, as by `order_groups`.
+    pub fn merge(&mut self, mut other: AllInFileVisitor<'_>) {
+        if let Some(progress_bar) = other.progress_bar.take() {
+            progress_bar.finish_and_clear();
+        }
+        let mut known: HashSet<PathBuf> = self.hash_files_map.values().flatten().cloned().collect();
+        known.extend(self.size_firstfile_map.values().flatten().cloned());
+        known.extend(self.ends_firstfile_map.values().flatten().cloned());
+        // Only the files of inodes both scans saw are looked at again, to tell
+        // whether they are links to data already counted here.
+        let shared: HashSet<DevIno> = self
+            .seen_inodes
+            .keys()
+            .filter(|inode| other.seen_inodes.contains_key(inode))
+            .copied()
+            .collect();
+        let other_dirs = &other.config.dirs;
+        let mut root_totals = std::mem::take(&mut other.root_totals);
+        let mut size_counts = std::mem::take(&mut other.size_counts);
+        let (mut skipped_hashed, mut skipped_linked) = (0, 0);
+        let mut skipped = RootTotals::default();
+        let mut is_new = |file: &Path, size: u64, hashed: bool| {
+            let linked = !shared.is_empty()
+                && DevIno::from_path(file).is_some_and(|inode| shared.contains(&inode));
+            if !linked && !known.contains(file) {
+                return true;
+            }
+            skipped.files += 1;
+            skipped.bytes += size;
+            if let Some(root) = root_totals.get_mut(root_index(other_dirs, file)) {
+                root.files -= 1;
+                root.bytes -= size;
+            }
+            if let Entry::Occupied(mut count) = size_counts.entry(size) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+            if linked {
+                skipped_linked += size;
+            }
+            if hashed {
+                skipped_hashed += 1;
+            }
+            false
+        };
+
+        // Files the other scan put aside, since no other file there had the
+        // same size, are compared as they would have been here.
+        for (size, first) in std::mem::take(&mut other.size_firstfile_map) {
+            match first {
+                Some(file) if is_new(&file, size, false) => {
+                    if let Entry::Vacant(entry) = self.size_firstfile_map.entry(size) {
+                        entry.insert(Some(file));
+                    } else {
+                        self.hash_first_of_size(size);
+                        self.hash_into_groups(file, size);
+                    }
+                }
+                Some(_) => (),
+                None => self.hash_first_of_size(size),
+            }
+        }
+        // Likewise the files put aside by their first and last blocks.
+        for (ends, first) in std::mem::take(&mut other.ends_firstfile_map) {
+            let first = first.filter(|file| is_new(file, ends.len(), false));
+            let waiting = match self.ends_firstfile_map.entry(ends) {
+                Entry::Vacant(entry) => {
+                    entry.insert(first);
+                    continue;
+                }
+                Entry::Occupied(mut entry) => entry.get_mut().take(),
+            };
+            for file in waiting.into_iter().chain(first) {
+                self.hash_whole(file, ends.len());
+            }
+        }
+        for (lenhash, paths) in std::mem::take(&mut other.hash_files_map) {
+            let paths: Vec<PathBuf> = paths
+                .into_iter()
+                .filter(|file| is_new(file, lenhash.len(), true))
+                .collect();
+            if !paths.is_empty() {
+                self.hash_files_map
+                    .entry(lenhash)
+                    .or_default()
+                    .extend(paths);
+            }
+        }
+        for (file, size) in std::mem::take(&mut other.pending) {
+            if is_new(&file, size, false) {
+                self.pending.push((file, size));
+            }
+        }
+        self.hash_pending();
+
+        self.total_file_bytes += other.total_file_bytes - skipped.bytes;
+        self.num_files += other.num_files - skipped.files;
+        self.linked_bytes += other.linked_bytes + skipped_linked;
+        self.num_hashed += other.num_hashed - skipped_hashed;
+        self.num_reused += other.num_reused;
+        self.broken_symlinks += other.broken_symlinks;
+        self.add_special_files(other.special_files);
+        self.unreadable.append(&mut other.unreadable);
+        self.unreadable_dirs.append(&mut other.unreadable_dirs);
+        self.archive_sizes.append(&mut other.archive_sizes);
+        self.path_inodes.append(&mut other.path_inodes);
+        self.file_times.append(&mut other.file_times);
+        self.seen_inodes.extend(other.seen_inodes.drain());
+        self.elapsed = self.elapsed.max(other.elapsed);
+        for (size, count) in size_counts {
+            *self.size_counts.entry(size).or_default() += count;
+        }
+        for (dir, totals) in other_dirs.iter().zip(root_totals) {
+            if let Some(root) = self.config.dirs.iter().position(|root| root == dir) {
+                self.root_totals[root].files += totals.files;
+                self.root_totals[root].bytes += totals.bytes;
+            }
+        }
+        if self.num_hashed > 0 {
+            self.order_groups();
+        }
+    }
+
+    // Shows how many files were found and hashed so far, and the file being
+    // scanned, cut to fit the width of the terminal.
+    fn show_progress(&mut self, file: &Path) {
+        let mut width = self.term.size_checked().unwrap_or((25, 40)).1 as usize;
+        let msg = format!(
+            "discovered: {}, hashed: {} {}",
+            self.num_files,
+            self.num_hashed,
+            file.to_str().unwrap_or("<invalid utf8>")
+        );
+        let expected_files = self.expected_files;
+        let progress_bar = self
+            .progress_bar
+            .get_or_insert_with(|| match expected_files {
+                Some(total) => {
+                    let progress_bar = ProgressBar::new(total);
+                    progress_bar
+                        .set_style(ProgressStyle::default_bar().template("{percent:>3}% {msg}"));
+                    progress_bar
+                }
+                None => ProgressBar::new_spinner(),
+            });
+        if expected_files.is_some() {
+            progress_bar.set_position(self.num_visited);
+            // Leave room for the percentage before the message.
+            width = width.saturating_sub(5);
+        }
+        progress_bar.set_message(output::cut_to_width(&msg, width));
+    }
+
+    // True if the progress bar hasn't been updated within the configured
+    // interval, in which case it is assumed it is about to be.
+    fn progress_due(&mut self) -> bool {
+        let now = Instant::now();
+        if let Some(last) = self.last_progress {
+            if now.duration_since(last) < self.config.progress_interval {
+                return false;
+            }
+        }
+        self.last_progress = Some(now);
+        true
+    }
+
+    // True if no --extensions were given, or if the file has one of them.
+    fn extension_matches(&self, file: &Path) -> bool {
+        match &self.config.extensions {
+            Some(extensions) => {
+                let ext = file
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                extensions.contains(&ext)
+            }
+            None => true,
+        }
+    }
+
+    // Ok if the file was modified within the --modified-after and
+    // --modified-before window, or otherwise why it is skipped. If a window
+    // is given but the modification time can't be read, the file is skipped
+    // since it can't be shown to be within the window.
+    fn modified_in_window(&self, meta: &Metadata) -> Result<(), Skip> {
+        self.modified_time_in_window(meta.modified())
+    }
+
+    // As modified_in_window, for a modification time read some other way,
+    // such as from an archive.
+    fn modified_time_in_window(&self, modified: io::Result<SystemTime>) -> Result<(), Skip> {
+        let config = self.config;
+        if config.modified_after.is_none() && config.modified_before.is_none() {
+            return Ok(());
+        }
+        let modified = modified.map_err(Skip::NoModifiedTime)?;
+        if let Some(after) = config.modified_after {
+            if modified < after {
+                return Err(Skip::OutsideModifiedWindow);
+            }
+        }
+        if let Some(before) = config.modified_before {
+            if modified >= before {
+                return Err(Skip::OutsideModifiedWindow);
+            }
+        }
+        Ok(())
+    }
+
+    // With --show-times, keeps when the file was modified and changed, for
+    // the results.
+    fn record_times(&mut self, file: &Path, times: FileTimes) {
+        if self.config.show_times {
+            self.file_times.insert(file.to_path_buf(), times);
+        }
+    }
+
+    // Adds the file to the totals, overall and for the directory it is in,
+    // and to the files of its size.
+    fn count_file(&mut self, file: &Path, size: u64) {
+        self.total_file_bytes += size;
+        self.num_files += 1;
+        *self.size_counts.entry(size).or_default() += 1;
+        if let Some(root) = self
+            .root_totals
+            .get_mut(root_index(&self.config.dirs, file))
+        {
+            root.files += 1;
+            root.bytes += size;
+        }
+    }
+
+    // Ok if files of the size are compared, or otherwise why they are skipped.
+    fn size_allowed(&self, size: u64) -> Result<(), Skip> {
+        if self.config.max_size.is_some_and(|max| size > max) {
+            return Err(Skip::TooLarge);
+        }
+        if size < self.config.min_size {
+            return Err(Skip::TooSmall);
+        }
+        let excluded = &self.config.excluded_sizes;
+        if excluded
+            .iter()
+            .any(|&(min, max)| min <= size && size <= max)
+        {
+            return Err(Skip::ExcludedSize);
+        }
+        if let Some(percent) = self.config.sample {
+            if !sampled(self.config.seed, size, percent) {
+                return Err(Skip::NotSampled);
+            }
+        }
+        Ok(())
+    }
+
+    // Compares the file with the others seen so far.
+    fn visit_file(&mut self, file: PathBuf) {
         match file.metadata() {
             Ok(meta) => {
+                if let Err(skip) = self.modified_in_window(&meta) {
+                    skip::log(self.config.verbosity, &file, skip);
+                    return;
+                }
+
                 let size = meta.len();
+                if let Err(skip) = self.size_allowed(size) {
+                    skip::log(self.config.verbosity, &file, skip);
+                    return;
+                }
 
                 if self.config.verbosity > 0 {
                     eprintln!("File: {:?} size: {}", file, size);
@@ -116,140 +828,503 @@ impl<'a> FileVisitor for AllInFileVisitor<'a> {
 
                 // If the inode that the file points at has at least one other file
                 // pointing at it, we should treat it special so that we don't hash
-                // the same data twice.
-                if has_hardlinks(&meta) {
+                // the same data twice. When following symlinks, any file could be
+                // reached again through a symlink, so all of them are tracked.
+                if has_hardlinks(&meta)
+                    || (self.config.follow_symlinks && cfg!(target_family = "unix"))
+                {
                     let inode = DevIno::from(&meta);
-                    let e = self.hardlinks_map.get_mut(&inode);
-                    // If there is already an entry for the dev+inode, then toss or
-                    // calculate hash, according to CLI option
-                    if let Some(_files) = e {
-                        // TODO Right now there is no CLI option to list hard links as
-                        // dupes. So we toss it.
-                        // I think something like this?
-                        // if list_hardlinks_as_dupes {
-                        //     if let Some(first) = hlink.first {
-                        //         let hash = hash_contents_path(&first);
-                        //         let paths = self.hash_files_map.entry(hash).or_insert_with(Vec::new);
-                        //         paths.push(first)
-                        //         hlink.first = None;
-                        //         hlink.hash = hash;
-                        //     } else {
-                        //         let paths = self.hash_files_map.entry(hlink.hash)
-                        //         paths.push(file);
-                        //     }
-                        // }
+                    if self.config.count_hardlinks {
+                        // Hard links are compared like any other file, but which
+                        // inode each is is kept, so that a group of nothing but
+                        // links to the same data isn't listed.
+                        self.path_inodes.insert(file.clone(), inode);
+                    } else if let Some(kept_len) = self.seen_inodes.get_mut(&inode) {
+                        // Another link to this data was already seen, so toss it,
+                        // though if its path is shorter, it stands for the data.
+                        let len = file.as_os_str().len();
+                        if len < *kept_len {
+                            *kept_len = len;
+                            self.record_times(&file, FileTimes::from_metadata(&meta));
+                            self.shorter_links.insert(inode, file);
+                        } else {
+                            skip::log(self.config.verbosity, &file, Skip::HardLinkSeen);
+                        }
+                        self.linked_bytes += size;
                         return;
                     } else {
-                        let files = LinkedFile::init(size, file.to_owned());
-                        self.hardlinks_map.insert(inode, files);
+                        self.seen_inodes.insert(inode, file.as_os_str().len());
                     }
                 }
 
-                self.total_file_bytes += size;
-                self.num_files += 1;
-
-                let e = self.size_firstfile_map.get(&size);
-                // If there is already an entry for the given size...
-                if let Some(inner_opt) = e {
-                    // ...and there is already a file with the given byte size, then hash that file
-                    // first, before hashing the current file.
-                    if let Some(original) = inner_opt {
-                        match hash_contents_path(&original) {
-                            Ok(hash) => {
-                                if self.config.verbosity > 0 {
-                                    eprintln!("\thash: {}", hash.to_hex());
-                                }
-                                let paths =
-                                    self.hash_files_map.entry(hash).or_insert_with(Vec::new);
-                                paths.push(original.clone());
-                                // (and replace the Some with None, so it won't be hashed again)
-                                self.size_firstfile_map.insert(size, None);
-                            }
-                            Err(e) => {
-                                eprintln!("Error: Could not hash {:?}: {}", original, e);
-                            }
-                        }
-                    }
-                    // ...now hash the current file.
-                    match hash_contents_path(&file) {
-                        Ok(hash) => {
+                self.count_file(&file, size);
+                self.record_times(&file, FileTimes::from_metadata(&meta));
+                if self.config.no_hash || self.config.size_histogram {
+                    return;
+                }
+
+                // Normalized text can match text of a different size, so it
+                // can't be put aside by size, and is hashed right away.
+                if self.config.normalize_text && size <= NORMALIZE_TEXT_MAX_SIZE {
+                    match hash_normalized_text(&file, size, &self.config.hashes) {
+                        Ok(Some(hash)) => {
                             if self.config.verbosity > 0 {
-                                eprintln!("\thash: {}", hash.to_hex());
+                                eprintln!("\tnormalized hash: {}", hash.to_hex());
                             }
-                            let paths = self.hash_files_map.entry(hash).or_insert_with(Vec::new);
-                            paths.push(file);
+                            self.add_to_group(hash, file);
+                            self.num_hashed += 1;
+                            return;
                         }
+                        // Binary files are compared as they are.
+                        Ok(None) => (),
                         Err(e) => {
-                            eprintln!("Error: Could not hash {:?}: {}", file, e);
+                            self.unreadable.push((file, e));
+                            return;
                         }
                     }
-                } else {
+                }
+
+                // Every file is listed in a manifest, or compared with one, so
+                // none are put aside by size.
+                if self.config.manifest || self.config.match_manifest.is_some() {
+                    self.hash_into_groups(file, size);
+                    return;
+                }
+
+                if let Entry::Vacant(entry) = self.size_firstfile_map.entry(size) {
                     // Since there isn't an entry for the given size, that means this is the first
                     // file with that size. Put it in the size map so that if another file with the
                     // same size is encountered, it can be hashed too.
-                    self.size_firstfile_map.insert(size, Some(file));
+                    entry.insert(Some(file));
+                    return;
                 }
+                // There is already an entry for the given size, so if the file with the given
+                // byte size hasn't been hashed, then hash that file first...
+                self.hash_first_of_size(size);
+                // ...now hash the current file.
+                self.hash_into_groups(file, size);
             }
-            Err(e) => {
-                eprintln!("Error: Could not get metadata for {:?}: {}", file, e);
+            // Files can be removed after they are found, so they're skipped
+            // quietly rather than reported as unreadable.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                skip::log(self.config.verbosity, &file, Skip::Vanished);
+            }
+            Err(e) => self.unreadable.push((file, e)),
+        }
+    }
+
+    // Hashes the file, and adds it to the files with the same hash. Large
+    // files are first compared by their first and last blocks, and the first
+    // file with those blocks is put aside until another has them too.
+    fn hash_into_groups(&mut self, file: PathBuf, size: u64) {
+        if self.checks_ends(size) {
+            let ends = match hash_ends_path(&file, size, ENDS_BLOCK_LEN, self.config.buffer_size) {
+                Ok(ends) => ends,
+                Err(e) => {
+                    self.unreadable.push((file, e));
+                    return;
+                }
+            };
+            match self.ends_firstfile_map.entry(ends) {
+                Entry::Vacant(entry) => {
+                    entry.insert(Some(file));
+                    return;
+                }
+                Entry::Occupied(mut entry) => {
+                    if let Some(first) = entry.get_mut().take() {
+                        self.hash_whole(first, size);
+                    }
+                }
+            }
+        }
+        self.hash_whole(file, size);
+    }
+
+    // True if files of the size are compared by their first and last blocks
+    // before being hashed whole. Not with --quick, which only hashes the
+    // first bytes anyway, nor for a manifest, which lists every file's hash,
+    // nor when comparing with one.
+    fn checks_ends(&self, size: u64) -> bool {
+        self.config.ends_check
+            && self.config.quick.is_none()
+            && !self.config.manifest
+            && self.config.match_manifest.is_none()
+            && size > 2 * ENDS_BLOCK_LEN
+            && !self.archive_sizes.contains(&size)
+    }
+
+    // Hashes the whole file, and adds it to the files with the same hash.
+    // With more than one --io-threads, it is put aside instead, to be hashed
+    // along with the others in `finish`, unless with --any, which needs to
+    // know of a duplicate as soon as there is one.
+    fn hash_whole(&mut self, file: PathBuf, size: u64) {
+        if self.config.io_threads > 1 && !self.config.any {
+            self.pending.push((file, size));
+            return;
+        }
+        let hashed = hash_file(self.config, self.adaptive_io.as_ref(), &file, size);
+        self.add_hashed(file, hashed);
+    }
+
+    // Adds the file to the files with the same hash, or if it couldn't be
+    // hashed, to those that couldn't be read.
+    fn add_hashed(&mut self, file: PathBuf, hashed: Hashed) {
+        match hashed {
+            Hashed::Read(Ok(hash)) | Hashed::Reused(hash) => {
+                if self.config.verbosity > 0 {
+                    eprintln!("\thash: {}", hash.to_hex());
+                }
+                if let Hashed::Reused(_) = hashed {
+                    self.num_reused += 1;
+                }
+                self.add_to_group(hash, file);
+                self.num_hashed += 1;
+            }
+            Hashed::Read(Err(e)) => self.unreadable.push((file, e)),
+        }
+    }
+
+    // Adds the hashed file to the files with the same hash. With --any, once
+    // that makes a group of duplicates, the visitor is done.
+    fn add_to_group(&mut self, hash: LenHash, file: PathBuf) {
+        let paths = self.hash_files_map.entry(hash).or_default();
+        paths.push(file);
+        if self.config.any
+            && paths.len() >= self.config.min_count
+            && !single_inode(paths, &self.path_inodes)
+        {
+            self.found_any = true;
+        }
+    }
+
+    // Hashes the files put aside for --io-threads, reading that many at once.
+    fn hash_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let config = self.config;
+        let adaptive_io = self.adaptive_io.as_ref();
+        let stop = self.stop;
+        let queue = Mutex::new(std::mem::take(&mut self.pending).into_iter());
+        let done = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..config.io_threads {
+                scope.spawn(|| loop {
+                    if stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                        break;
+                    }
+                    let next = queue.lock().unwrap().next();
+                    let (file, size) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let hashed = hash_file(config, adaptive_io, &file, size);
+                    done.lock().unwrap().push((file, hashed));
+                });
             }
+        });
+        // Files finish in whatever order the threads get to them, so they are
+        // sorted to add them the same way every time.
+        let mut done = done.into_inner().unwrap_or_else(|e| e.into_inner());
+        done.sort_by(|a, b| a.0.cmp(&b.0));
+        for (file, hashed) in done {
+            self.add_hashed(file, hashed);
+        }
+    }
+
+    // If the first file of the given size hasn't been hashed yet, because no
+    // other file of that size had been seen, then hash it now.
+    fn hash_first_of_size(&mut self, size: u64) {
+        if let Some(Some(original)) = self.size_firstfile_map.insert(size, None) {
+            self.hash_into_groups(original, size);
+        }
+    }
+
+    // Compares each file within the archive with the others seen so far.
+    fn visit_archive(&mut self, archive: &Path) {
+        let result = archive::for_each_entry(archive, &mut |entry| {
+            self.visit_archive_entry(archive, entry)
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: Could not scan archive {:?}: {}", archive, e);
+        }
+    }
+
+    // Entries within an archive can't be reopened later like files can, so
+    // they are always hashed right away, and the first file of the same size
+    // (if any) is hashed too so the two can be compared, as are any files of
+    // the size waiting to be compared by their blocks. They are left out by
+    // the same rules as files, with the directories within the archive
+    // checked for --exclude and hidden names as a walk would check them.
+    fn visit_archive_entry(&mut self, archive: &Path, entry: archive::Entry) {
+        let path = entry.path.clone();
+        if self.config.skip_hidden && entry.is_hidden() {
+            skip::log(self.config.verbosity, &path, Skip::Hidden);
+            return;
+        }
+        if let Some(excluded) = entry
+            .ancestors()
+            .into_iter()
+            .map(|name| (archive::entry_path(archive, name), name))
+            .find(|(path, name)| {
+                skip::is_excluded(path, self.config) || skip::is_name_excluded(name, self.config)
+            })
+            .map(|(path, _)| path)
+        {
+            skip::log(self.config.verbosity, &excluded, Skip::Excluded);
+            return;
+        }
+        if !self.extension_matches(&path) {
+            skip::log(self.config.verbosity, &path, Skip::Extension);
+            return;
+        }
+        let modified = entry
+            .modified
+            .ok_or_else(|| io::Error::other("not recorded in the archive"));
+        if let Err(skip) = self.modified_time_in_window(modified) {
+            skip::log(self.config.verbosity, &path, skip);
+            return;
+        }
+        let size = entry.size;
+        if let Err(skip) = self.size_allowed(size) {
+            skip::log(self.config.verbosity, &path, skip);
+            return;
+        }
+        let contents = entry.contents;
+        if self.config.verbosity > 0 {
+            eprintln!("File: {:?} size: {}", path, size);
+        }
+
+        self.count_file(&path, size);
+        self.record_times(
+            &path,
+            FileTimes {
+                modified: entry.modified,
+                changed: None,
+            },
+        );
+        if self.config.no_hash || self.config.size_histogram {
+            return;
+        }
+
+        self.archive_sizes.insert(size);
+        self.hash_first_of_size(size);
+        let waiting: Vec<LenHash> = self
+            .ends_firstfile_map
+            .range(LenHash::from(size, [u8::MAX; 32])..=LenHash::from(size, [0; 32]))
+            .map(|(ends, _)| *ends)
+            .collect();
+        for ends in waiting {
+            if let Some(Some(first)) = self.ends_firstfile_map.remove(&ends) {
+                self.hash_whole(first, size);
+            }
+        }
+        let hashed = match self.config.quick {
+            Some(prefix_len) => hash_prefix_reader(
+                size,
+                prefix_len,
+                contents,
+                self.config.buffer_size,
+                &self.config.hashes,
+            ),
+            None => {
+                hash_contents_reader(size, contents, self.config.buffer_size, &self.config.hashes)
+            }
+        };
+        match hashed {
+            Ok(hash) => {
+                if self.config.verbosity > 0 {
+                    eprintln!("\thash: {}", hash.to_hex());
+                }
+                self.add_to_group(hash, path);
+                self.num_hashed += 1;
+            }
+            Err(e) => self.unreadable.push((path, e)),
         }
     }
 }
 
+// The files that one of the configured directories contributed to the scan,
+// their bytes, and how many of them are duplicates.
+#[derive(std::fmt::Debug, std::default::Default, std::clone::Clone)]
+pub struct RootTotals {
+    pub files: u32,
+    pub bytes: u64,
+    pub duplicates: usize,
+}
+
+// True if files of the size are in the --sample. Sizes rather than files are
+// sampled, so that all the files that could be duplicates of each other are
+// in or out together, and the duplicates found scale with the sample like the
+// files do. The same seed always picks the same sizes.
+fn sampled(seed: u64, size: u64, percent: f64) -> bool {
+    let pick = splitmix64(seed ^ splitmix64(size));
+    (pick as f64 / u64::MAX as f64) * 100.0 < percent
+}
+
+// Mixes the bits of x, as the SplitMix64 generator does for each number.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// The hash of a file, either read from it now, or reused from --since.
+enum Hashed {
+    Read(io::Result<LenHash>),
+    Reused(LenHash),
+}
+
+// Hashes the whole file, or with --quick, only its first bytes. If the file
+// hasn't changed since the --since result, its hash from then is used.
+fn hash_file(config: &Config, adaptive_io: Option<&AdaptiveIo>, file: &Path, size: u64) -> Hashed {
+    if let Some(hash) = config
+        .since
+        .as_ref()
+        .and_then(|since| since.hash_of(file, size))
+    {
+        return Hashed::Reused(hash);
+    }
+    Hashed::Read(match config.quick {
+        Some(prefix_len) => {
+            hash_prefix_path(file, size, prefix_len, config.buffer_size, &config.hashes)
+        }
+        None => match adaptive_io {
+            Some(adaptive_io) => hash_contents_path_adaptive(
+                file,
+                Some(size),
+                adaptive_io,
+                config.buffer_size,
+                &config.hashes,
+            ),
+            None => hash_contents_path_using(
+                file,
+                Some(size),
+                config.mmap_threshold,
+                config.buffer_size,
+                &config.hashes,
+            ),
+        },
+    })
+}
+
+impl<'a> FileVisitor for AllInFileVisitor<'a> {
+    fn visit(&mut self, file: PathBuf) {
+        self.visit_path(file);
+    }
+
+    fn done(&self) -> bool {
+        self.found_any
+    }
+}
+
 impl<'a> IntoIterator for &'a AllInFileVisitor<'a> {
     type Item = (&'a LenHash, &'a std::vec::Vec<PathBuf>);
-    type IntoIter = std::iter::Filter<
-        std::collections::btree_map::Iter<'a, LenHash, std::vec::Vec<PathBuf>>,
-        for<'r> fn(&'r (&LenHash, &std::vec::Vec<std::path::PathBuf>)) -> bool,
-    >;
+    type IntoIter = DupeGroups<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.hash_files_map.iter().filter(only_with_dupes)
+        DupeGroups {
+            iter: self.hash_files_map.iter(),
+            min_count: self.config.min_count,
+            path_inodes: &self.path_inodes,
+        }
     }
 }
 
-// If this file is a hardlink, then return true.
-#[cfg(target_family = "unix")]
-fn has_hardlinks(meta: &dyn std::os::unix::fs::MetadataExt) -> bool {
-    meta.nlink() > 1
+// A group of identical files, the original first, with what the scan found
+// out about them.
+pub struct Group<'a> {
+    pub lenhash: &'a LenHash,
+    pub paths: &'a [PathBuf],
+    // Bytes taken up by the duplicates, as by `AllInFileVisitor::wasted`.
+    pub wasted: u64,
+    // With --show-times, when each of the files was modified and changed,
+    // as read during the scan, in the order of the paths. Empty otherwise.
+    pub times: Vec<FileTimes>,
 }
 
-// If this file is a hardlink, then return true.
-#[cfg(target_family = "windows")]
-fn has_hardlinks(meta: &fs::Metadata) -> bool {
-    // This is possible in Windows, but for now skip it.
-    false
+// Iterates over the groups of identical files, skipping any group with fewer
+// than min_count files in it, and any group of hard links to just one inode.
+pub struct DupeGroups<'a> {
+    iter: std::collections::btree_map::Iter<'a, LenHash, std::vec::Vec<PathBuf>>,
+    min_count: usize,
+    path_inodes: &'a BTreeMap<PathBuf, DevIno>,
 }
 
-fn hash_contents_path(file: &Path) -> io::Result<LenHash> {
-    let file = File::open(file)?;
-    let size = file.metadata()?.len();
+impl<'a> Iterator for DupeGroups<'a> {
+    type Item = (&'a LenHash, &'a std::vec::Vec<PathBuf>);
 
-    if size >= 16384 && size <= isize::max_value() as u64 {
-        hash_contents_mmap(size, &file)
-    } else {
-        hash_contents_file(size, file)
+    fn next(&mut self) -> Option<Self::Item> {
+        let min_count = self.min_count;
+        let path_inodes = self.path_inodes;
+        self.iter
+            .find(|x| x.1.len() >= min_count && !single_inode(x.1, path_inodes))
     }
 }
 
-fn hash_contents_file(size: u64, file: File) -> io::Result<LenHash> {
-    let mut file = file;
-    let mut hasher = blake3::Hasher::new();
-    std::io::copy(&mut file, &mut hasher)?;
+// True if every path is a hard link to the same inode, so the group has no
+// real duplication.
+fn single_inode(paths: &[PathBuf], path_inodes: &BTreeMap<PathBuf, DevIno>) -> bool {
+    let mut inodes = paths.iter().map(|path| path_inodes.get(path));
+    match inodes.next() {
+        Some(Some(first)) => inodes.all(|inode| inode == Some(first)),
+        _ => false,
+    }
+}
+
+// Number of distinct files in the group, counting hard links to the same
+// inode once. Paths without a known inode are each their own.
+fn distinct_inodes(paths: &[PathBuf], path_inodes: &BTreeMap<PathBuf, DevIno>) -> usize {
+    let inodes: HashSet<&DevIno> = paths
+        .iter()
+        .filter_map(|path| path_inodes.get(path))
+        .collect();
+    let unknown = paths
+        .iter()
+        .filter(|path| !path_inodes.contains_key(*path))
+        .count();
+    inodes.len() + unknown
+}
 
-    Ok(LenHash::from(size, hasher.finalize().into()))
+// The position of the first of the given directories (or files) that the
+// path was found under, so files found under earlier ones come first.
+fn root_index(roots: &[PathBuf], path: &Path) -> usize {
+    roots
+        .iter()
+        .position(|root| path.starts_with(root))
+        .unwrap_or(roots.len())
 }
 
-fn hash_contents_mmap(size: u64, file: &File) -> io::Result<LenHash> {
-    let mmap = unsafe { MmapOptions::new().map(&file)? };
+// Orders paths alphabetically, component by component. If ignoring case, then
+// paths that only differ by case are ordered as they would be otherwise.
+fn alphabetical_key(path: &Path, ignore_case: bool) -> (Vec<String>, PathBuf) {
+    let folded = if ignore_case {
+        path.iter()
+            .map(|part| part.to_string_lossy().to_lowercase())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    (folded, path.to_path_buf())
+}
 
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(&mmap);
+// The modification time of the file, if it can be read. Files without one
+// sort after those that have one, so they aren't picked as the original.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|meta| meta.modified()).ok()
+}
 
-    Ok(LenHash::from(size, hasher.finalize().into()))
+// If this file is a hardlink, then return true.
+#[cfg(target_family = "unix")]
+fn has_hardlinks(meta: &dyn std::os::unix::fs::MetadataExt) -> bool {
+    meta.nlink() > 1
 }
 
-fn only_with_dupes<'r>(x: &'r (&LenHash, &std::vec::Vec<PathBuf>)) -> bool {
-    x.1.len() > 1
+// If this file is a hardlink, then return true.
+#[cfg(target_family = "windows")]
+fn has_hardlinks(_meta: &Metadata) -> bool {
+    // This is possible in Windows, but the link count isn't available from
+    // std's Metadata on stable Rust, so for now skip it.
+    false
 }
@@ -0,0 +1,495 @@
+use crate::lsdup::lenhash::LenHash;
+use memmap::MmapOptions;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Files of at least this many bytes are memory mapped by default.
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 16384;
+
+/// Size of the buffer used by default when reading files.
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+
+// Files larger than twice this size have their first and last this many
+// bytes compared before being hashed whole. Smaller files are about as quick
+// to hash whole.
+pub const ENDS_BLOCK_LEN: u64 = 64 * 1024;
+
+// Only text files up to this size are normalized, since they are read into
+// memory whole.
+pub const NORMALIZE_TEXT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// An algorithm files can be hashed with to compare them. BLAKE3 is used
+/// unless others are chosen with --hash.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The name of the algorithm, as given to --hash.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Number of bytes in a digest of the algorithm.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Blake3 => blake3::OUT_LEN,
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        match name {
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+// Hashes the same data with each of the algorithms, so that files only need
+// to be read once however many are used. The first algorithm gives the hash,
+// and the second, if any, the extra digest that has to match too.
+struct Hasher {
+    hashers: Vec<AnyHasher>,
+}
+
+enum AnyHasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithms: &[HashAlgorithm]) -> Hasher {
+        let hashers = algorithms
+            .iter()
+            .map(|algorithm| match algorithm {
+                HashAlgorithm::Blake3 => AnyHasher::Blake3(Box::new(blake3::Hasher::new())),
+                HashAlgorithm::Sha256 => AnyHasher::Sha256(Sha256::new()),
+            })
+            .collect();
+        Hasher { hashers }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for hasher in &mut self.hashers {
+            match hasher {
+                AnyHasher::Blake3(hasher) => {
+                    hasher.update(data);
+                }
+                AnyHasher::Sha256(hasher) => hasher.update(data),
+            }
+        }
+    }
+
+    fn finalize(self, len: u64) -> LenHash {
+        let mut digests = self.hashers.into_iter().map(|hasher| -> [u8; 32] {
+            match hasher {
+                AnyHasher::Blake3(hasher) => hasher.finalize().into(),
+                AnyHasher::Sha256(hasher) => hasher.finalize().into(),
+            }
+        });
+        let hash = digests.next().unwrap_or_default();
+        match digests.next() {
+            Some(extra) => LenHash::with_extra(len, hash, extra),
+            None => LenHash::from(len, hash),
+        }
+    }
+}
+
+/// Hashes the contents of the file with BLAKE3, using the default memory
+/// mapping threshold and buffer size. See `hash_contents_path_with`.
+pub fn hash_contents_path(file: &Path) -> io::Result<LenHash> {
+    hash_contents_path_with(file, None, DEFAULT_MMAP_THRESHOLD, DEFAULT_BUFFER_SIZE)
+}
+
+/// Hashes the contents of the file with BLAKE3, returning its length and
+/// hash.
+///
+/// Files of at least `mmap_threshold` bytes are memory mapped and hashed in
+/// one pass, which is usually fastest for larger files. Smaller files, empty
+/// files (which can't be mapped), and files larger than `isize::MAX` bytes
+/// (which can't be addressed, so on 32-bit platforms, files of 2 GiB or more)
+/// are read through a buffer of `buffer_size` bytes instead. If mapping a
+/// file fails anyway, such as when a 32-bit process doesn't have that much
+/// address space free, the file is read through the buffer too.
+///
+/// If `expected_size` is given and the file is not that size, or changes size
+/// while being read, then it is being modified and its hash can't be trusted,
+/// so an error is returned.
+pub fn hash_contents_path_with(
+    file: &Path,
+    expected_size: Option<u64>,
+    mmap_threshold: u64,
+    buffer_size: usize,
+) -> io::Result<LenHash> {
+    hash_contents_path_using(
+        file,
+        expected_size,
+        mmap_threshold,
+        buffer_size,
+        &[HashAlgorithm::Blake3],
+    )
+}
+
+// As hash_contents_path_with, hashing with each of the algorithms.
+pub(crate) fn hash_contents_path_using(
+    file: &Path,
+    expected_size: Option<u64>,
+    mmap_threshold: u64,
+    buffer_size: usize,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<LenHash> {
+    let file = File::open(file)?;
+    let size = file.metadata()?.len();
+    if let Some(expected_size) = expected_size {
+        if size != expected_size {
+            return Err(size_changed(expected_size, size));
+        }
+    }
+
+    if should_mmap(size, mmap_threshold, MMAP_MAX_SIZE) {
+        if let Ok(hash) = hash_contents_mmap(size, &file, algorithms) {
+            return Ok(hash);
+        }
+    }
+    let mut file = file;
+    hash_contents_reader(size, &mut file, buffer_size, algorithms)
+}
+
+/// How a file is read to be hashed.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy, std::cmp::PartialEq)]
+pub enum ReadStrategy {
+    /// Memory mapped and hashed in one pass.
+    Mmap,
+    /// Read through a buffer.
+    Buffered,
+}
+
+/// Chooses between memory mapping files and reading them through a buffer by
+/// how fast each turns out to be on the files being scanned, for
+/// --adaptive-io, instead of by a fixed size threshold. The first files are
+/// read each way in turn, and once enough have been read both ways, the
+/// faster way is used for the rest. It can be shared by the threads reading
+/// files.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct AdaptiveIo {
+    // The files, bytes, and time taken reading each way so far, memory
+    // mapped first. Only kept until one is chosen.
+    trials: Mutex<[Trial; 2]>,
+    chosen: OnceLock<ReadStrategy>,
+}
+
+#[derive(std::fmt::Debug, std::default::Default, std::clone::Clone, std::marker::Copy)]
+struct Trial {
+    files: u32,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+impl Trial {
+    // Bytes read per second, or None if nothing has been read yet.
+    fn throughput(&self) -> Option<f64> {
+        if self.files == 0 {
+            return None;
+        }
+        Some(self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9))
+    }
+}
+
+// Files read each way before choosing the faster one.
+const ADAPTIVE_TRIAL_FILES: u32 = 8;
+
+impl AdaptiveIo {
+    pub fn new() -> AdaptiveIo {
+        AdaptiveIo::default()
+    }
+
+    /// The way that was chosen, or None if too few files were read to choose.
+    pub fn chosen(&self) -> Option<ReadStrategy> {
+        self.chosen.get().copied()
+    }
+
+    /// Bytes per second read the given way while choosing, or None if no
+    /// file was read that way.
+    pub fn throughput(&self, strategy: ReadStrategy) -> Option<f64> {
+        self.trials.lock().unwrap()[strategy as usize].throughput()
+    }
+
+    // The way to read the next file: the chosen way, or while still
+    // choosing, whichever way fewer files have been read.
+    fn next(&self) -> ReadStrategy {
+        if let Some(chosen) = self.chosen() {
+            return chosen;
+        }
+        let trials = self.trials.lock().unwrap();
+        if trials[ReadStrategy::Mmap as usize].files
+            <= trials[ReadStrategy::Buffered as usize].files
+        {
+            ReadStrategy::Mmap
+        } else {
+            ReadStrategy::Buffered
+        }
+    }
+
+    // Adds a file that was read the given way, and once enough files were
+    // read both ways, chooses the faster.
+    fn record(&self, strategy: ReadStrategy, bytes: u64, elapsed: Duration) {
+        if self.chosen().is_some() {
+            return;
+        }
+        let mut trials = self.trials.lock().unwrap();
+        let trial = &mut trials[strategy as usize];
+        trial.files += 1;
+        trial.bytes += bytes;
+        trial.elapsed += elapsed;
+        let [mmap, buffered] = *trials;
+        if mmap.files < ADAPTIVE_TRIAL_FILES || buffered.files < ADAPTIVE_TRIAL_FILES {
+            return;
+        }
+        let faster = if mmap.throughput() >= buffered.throughput() {
+            ReadStrategy::Mmap
+        } else {
+            ReadStrategy::Buffered
+        };
+        let _ = self.chosen.set(faster);
+    }
+}
+
+// As hash_contents_path_using, but whether the file is memory mapped is
+// chosen by the adaptive reader rather than by a threshold, and the time it
+// took is told to it.
+pub(crate) fn hash_contents_path_adaptive(
+    file: &Path,
+    expected_size: Option<u64>,
+    adaptive: &AdaptiveIo,
+    buffer_size: usize,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<LenHash> {
+    let strategy = adaptive.next();
+    let mmap_threshold = match strategy {
+        ReadStrategy::Mmap => 0,
+        ReadStrategy::Buffered => u64::MAX,
+    };
+    let started = Instant::now();
+    let hash =
+        hash_contents_path_using(file, expected_size, mmap_threshold, buffer_size, algorithms)?;
+    // Empty files are never mapped, so they tell nothing of either way.
+    if !hash.is_empty() {
+        adaptive.record(strategy, hash.len(), started.elapsed());
+    }
+    Ok(hash)
+}
+
+// A mapping must fit within the address space, and Rust slices can't be
+// longer than isize::MAX bytes, so larger files are never mapped. This is
+// 2 GiB - 1 on 32-bit platforms, and far larger than any file on 64-bit ones.
+const MMAP_MAX_SIZE: u64 = isize::MAX as u64;
+
+// True if a file of the size should be memory mapped rather than read.
+fn should_mmap(size: u64, mmap_threshold: u64, max_size: u64) -> bool {
+    size > 0 && size >= mmap_threshold && size <= max_size
+}
+
+// Hashes everything read from the reader, which is expected to be the given
+// size.
+pub(crate) fn hash_contents_reader(
+    size: u64,
+    contents: &mut dyn Read,
+    buffer_size: usize,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<LenHash> {
+    let mut hasher = Hasher::new(algorithms);
+    hash_reader(size, contents, buffer_size, &mut hasher)?;
+    Ok(hasher.finalize(size))
+}
+
+// Hashes only the first prefix_len bytes of the file, or all of it if it is
+// shorter. The length is still that of the whole file, so that only files of
+// the same size can match.
+pub(crate) fn hash_prefix_path(
+    file: &Path,
+    size: u64,
+    prefix_len: u64,
+    buffer_size: usize,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<LenHash> {
+    let mut file = File::open(file)?;
+    hash_prefix_reader(size, prefix_len, &mut file, buffer_size, algorithms)
+}
+
+// As hash_prefix_path, for contents that are already open.
+pub(crate) fn hash_prefix_reader(
+    size: u64,
+    prefix_len: u64,
+    contents: &mut dyn Read,
+    buffer_size: usize,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<LenHash> {
+    let mut prefix = contents.take(prefix_len);
+    let mut hasher = Hasher::new(algorithms);
+    hash_reader(size.min(prefix_len), &mut prefix, buffer_size, &mut hasher)?;
+    Ok(hasher.finalize(size))
+}
+
+// Hashes the first and last block_len bytes of the file, which is expected to
+// be larger than both blocks together. Files that share a header, such as
+// media of the same format, usually still differ at the end. The length is
+// that of the whole file, so that only files of the same size can match.
+pub(crate) fn hash_ends_path(
+    file: &Path,
+    size: u64,
+    block_len: u64,
+    buffer_size: usize,
+) -> io::Result<LenHash> {
+    let mut file = File::open(file)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut reader = BufReader::with_capacity(buffer_size, &mut file);
+    io::copy(&mut (&mut reader).take(block_len), &mut hasher)?;
+    reader.seek(SeekFrom::Start(size - block_len))?;
+    let read = io::copy(&mut reader.take(block_len), &mut hasher)?;
+    if read != block_len {
+        return Err(size_changed(size, size - block_len + read));
+    }
+    Ok(LenHash::from(size, hasher.finalize().into()))
+}
+
+fn hash_reader(
+    size: u64,
+    contents: &mut dyn Read,
+    buffer_size: usize,
+    hasher: &mut Hasher,
+) -> io::Result<()> {
+    let mut reader = BufReader::with_capacity(buffer_size, contents);
+    let mut read: u64 = 0;
+    loop {
+        let buf = match reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if buf.is_empty() {
+            break;
+        }
+        hasher.update(buf);
+        let len = buf.len();
+        read += len as u64;
+        reader.consume(len);
+    }
+    if read != size {
+        return Err(size_changed(size, read));
+    }
+    Ok(())
+}
+
+// Hashes the file as normalized text, so that files that only differ by line
+// endings or trailing whitespace have the same hash. The length is that of
+// the normalized text. None if the file looks like binary, since it has a NUL
+// byte, and so shouldn't be normalized.
+pub(crate) fn hash_normalized_text(
+    file: &Path,
+    size: u64,
+    algorithms: &[HashAlgorithm],
+) -> io::Result<Option<LenHash>> {
+    let contents = std::fs::read(file)?;
+    if contents.len() as u64 != size {
+        return Err(size_changed(size, contents.len() as u64));
+    }
+    if contents.contains(&0) {
+        return Ok(None);
+    }
+    let text = normalize_text(&contents);
+    let mut hasher = Hasher::new(algorithms);
+    hasher.update(&text);
+    Ok(Some(hasher.finalize(text.len() as u64)))
+}
+
+// Joins the lines with LF line endings, without trailing spaces or tabs, and
+// without blank lines at the end.
+fn normalize_text(contents: &[u8]) -> Vec<u8> {
+    let mut text = Vec::with_capacity(contents.len());
+    for line in contents.split(|b| *b == b'\n') {
+        let end = line
+            .iter()
+            .rposition(|b| !matches!(b, b' ' | b'\t' | b'\r'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        text.extend_from_slice(&line[..end]);
+        text.push(b'\n');
+    }
+    while text.last() == Some(&b'\n') {
+        text.pop();
+    }
+    text
+}
+
+fn size_changed(expected_size: u64, size: u64) -> io::Error {
+    io::Error::other(format!(
+        "file changed size from {} to {} bytes while being scanned",
+        expected_size, size
+    ))
+}
+
+fn hash_contents_mmap(size: u64, file: &File, algorithms: &[HashAlgorithm]) -> io::Result<LenHash> {
+    let mmap = unsafe { MmapOptions::new().map(file)? };
+
+    let mut hasher = Hasher::new(algorithms);
+    hasher.update(&mmap);
+
+    Ok(hasher.finalize(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_io_chooses_faster() {
+        let adaptive = AdaptiveIo::new();
+
+        // While choosing, files are read each way in turn,
+        for _ in 0..ADAPTIVE_TRIAL_FILES {
+            assert_eq!(ReadStrategy::Mmap, adaptive.next());
+            adaptive.record(ReadStrategy::Mmap, 1000, Duration::from_millis(2));
+            assert_eq!(None, adaptive.chosen());
+            assert_eq!(ReadStrategy::Buffered, adaptive.next());
+            adaptive.record(ReadStrategy::Buffered, 1000, Duration::from_millis(1));
+        }
+
+        // and once enough were read both ways, the faster is used for the rest.
+        assert_eq!(Some(ReadStrategy::Buffered), adaptive.chosen());
+        assert_eq!(ReadStrategy::Buffered, adaptive.next());
+        assert!(
+            adaptive.throughput(ReadStrategy::Mmap) < adaptive.throughput(ReadStrategy::Buffered)
+        );
+    }
+
+    #[test]
+    fn test_should_mmap_limit() {
+        // As on a 32-bit platform, where isize::MAX is i32::MAX.
+        let max_size = i32::MAX as u64;
+        assert!(should_mmap(max_size, DEFAULT_MMAP_THRESHOLD, max_size));
+        assert!(!should_mmap(max_size + 1, DEFAULT_MMAP_THRESHOLD, max_size));
+        assert!(!should_mmap(
+            4 * 1024 * 1024 * 1024,
+            DEFAULT_MMAP_THRESHOLD,
+            max_size
+        ));
+        assert!(!should_mmap(0, 0, max_size));
+        assert!(!should_mmap(
+            DEFAULT_MMAP_THRESHOLD - 1,
+            DEFAULT_MMAP_THRESHOLD,
+            max_size
+        ));
+    }
+}
@@ -1,29 +1,116 @@
 use arrayvec::ArrayString;
 
-#[derive(std::hash::Hash, std::cmp::Eq, std::cmp::PartialEq, std::fmt::Debug)]
+#[derive(
+    std::hash::Hash,
+    std::cmp::Eq,
+    std::cmp::PartialEq,
+    std::clone::Clone,
+    std::marker::Copy,
+    std::fmt::Debug,
+)]
 pub struct LenHash {
     len: u64,
     hash: [u8; 32],
+    // With a second --hash algorithm, its digest, which must match too.
+    extra: Option<[u8; 32]>,
 }
 
 impl LenHash {
     pub fn from(len: u64, hash: [u8; 32]) -> LenHash {
-        LenHash { len, hash }
+        LenHash {
+            len,
+            hash,
+            extra: None,
+        }
+    }
+
+    // With the digest of a second algorithm, so both have to match.
+    pub fn with_extra(len: u64, hash: [u8; 32], extra: [u8; 32]) -> LenHash {
+        LenHash {
+            len,
+            hash,
+            extra: Some(extra),
+        }
+    }
+
+    // Reads the hash back from the 64 hex digits that to_hex writes. None if
+    // it isn't exactly that.
+    pub fn from_hex(len: u64, hex: &str) -> Option<LenHash> {
+        let hex = hex.as_bytes();
+        if hex.len() != 64 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        let mut hash = [0u8; 32];
+        for (i, b) in hash.iter_mut().enumerate() {
+            let digits = std::str::from_utf8(&hex[i * 2..i * 2 + 2]).ok()?;
+            *b = u8::from_str_radix(digits, 16).ok()?;
+        }
+        Some(LenHash::from(len, hash))
     }
 
     pub fn len(&self) -> u64 {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Orders by length, smallest first, and then by hash, lowest first. The
+    // reverse of the usual order, for when groups are wanted smallest first.
+    pub fn cmp_ascending(&self, other: &Self) -> std::cmp::Ordering {
+        other.cmp(self)
+    }
+
     pub fn to_hex(&self) -> ArrayString<64> {
-        // As done in Blake3 to_hex function.
-        let mut s = ArrayString::new();
-        let table = b"0123456789abcdef";
-        for &b in self.hash.iter() {
-            s.push(table[(b >> 4) as usize] as char);
-            s.push(table[(b & 0xf) as usize] as char);
-        }
-        s
+        to_hex(&self.hash)
+    }
+
+    // The same size and hash, without the digest of a second algorithm, as
+    // when read back from output that only lists the first.
+    pub fn without_extra(&self) -> LenHash {
+        LenHash::from(self.len, self.hash)
+    }
+
+    // The digest of the second --hash algorithm, if one was given.
+    pub fn extra_hex(&self) -> Option<ArrayString<64>> {
+        self.extra.as_ref().map(to_hex)
+    }
+}
+
+fn to_hex(hash: &[u8; 32]) -> ArrayString<64> {
+    // As done in Blake3 to_hex function.
+    let mut s = ArrayString::new();
+    let table = b"0123456789abcdef";
+    for &b in hash.iter() {
+        s.push(table[(b >> 4) as usize] as char);
+        s.push(table[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+// A LenHash that orders smallest length first, then by hash, such as for a
+// BTreeMap of groups to be listed smallest first. The LenHash itself keeps
+// ordering largest first, as the results are listed.
+#[derive(
+    std::hash::Hash,
+    std::cmp::Eq,
+    std::cmp::PartialEq,
+    std::clone::Clone,
+    std::marker::Copy,
+    std::fmt::Debug,
+)]
+pub struct Ascending(pub LenHash);
+
+impl Ord for Ascending {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp_ascending(&other.0)
+    }
+}
+
+impl PartialOrd for Ascending {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -35,6 +122,7 @@ impl Ord for LenHash {
             .len
             .cmp(&self.len)
             .then_with(|| other.hash.cmp(&self.hash))
+            .then_with(|| other.extra.cmp(&self.extra))
     }
 }
 
@@ -43,3 +131,32 @@ impl PartialOrd for LenHash {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascending() {
+        let small = LenHash::from(10, [0xff; 32]);
+        let large_low = LenHash::from(20, [0x00; 32]);
+        let large_high = LenHash::from(20, [0x01; 32]);
+
+        // The usual order is largest first,
+        let mut usual = vec![small, large_low, large_high];
+        usual.sort();
+        assert_eq!(vec![large_high, large_low, small], usual);
+
+        // while ascending is smallest first, then by hash.
+        let mut ascending: Vec<_> = usual.iter().copied().map(Ascending).collect();
+        ascending.sort();
+        assert_eq!(
+            vec![
+                Ascending(small),
+                Ascending(large_low),
+                Ascending(large_high)
+            ],
+            ascending
+        );
+    }
+}
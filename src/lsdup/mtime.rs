@@ -0,0 +1,221 @@
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// When a file was last modified, and when its status, such as its contents,
+// name, or permissions, last changed, as read during the scan. Windows doesn't
+// keep the changed time, and archives don't record it, so there it is None.
+#[derive(std::fmt::Debug, std::default::Default, Clone, Copy, PartialEq)]
+pub struct FileTimes {
+    pub modified: Option<SystemTime>,
+    pub changed: Option<SystemTime>,
+}
+
+impl FileTimes {
+    pub fn from_metadata(meta: &Metadata) -> FileTimes {
+        FileTimes {
+            modified: meta.modified().ok(),
+            changed: changed_time(meta),
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn changed_time(meta: &Metadata) -> Option<SystemTime> {
+    use std::convert::TryFrom;
+    use std::os::unix::fs::MetadataExt;
+
+    let secs = u64::try_from(meta.ctime()).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, meta.ctime_nsec() as u32))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn changed_time(_meta: &Metadata) -> Option<SystemTime> {
+    None
+}
+
+// Parses a point in time given on the commandline. Either an ISO-8601 date
+// or date and time in UTC ("2023-01-15", "2023-01-15T10:30:00Z"), or an age
+// relative to now, made of a whole number and a unit of s, m, h, d, or w
+// ("7d" is seven days ago).
+pub fn parse(value: &str, now: SystemTime) -> Result<SystemTime, &'static str> {
+    let value = value.trim();
+    if let Some(age) = parse_age(value) {
+        return now
+            .checked_sub(age)
+            .ok_or("relative time is too far in the past");
+    }
+    parse_iso8601(value)
+}
+
+fn parse_age(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount = value[..value.len() - 1].parse::<u64>().ok()?;
+    Some(Duration::from_secs(amount.checked_mul(multiplier)?))
+}
+
+fn parse_iso8601(value: &str) -> Result<SystemTime, &'static str> {
+    const BAD_DATE: &str = "expected a date like 2023-01-15, 2023-01-15T10:30:00Z, or 7d";
+
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date, time) = match value.find(['T', ' ']) {
+        Some(i) => (&value[..i], Some(&value[i + 1..])),
+        None => (value, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-');
+    let year = next_number(&mut date_parts).ok_or(BAD_DATE)?;
+    let month = next_number(&mut date_parts).ok_or(BAD_DATE)?;
+    let day = next_number(&mut date_parts).ok_or(BAD_DATE)?;
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day)
+    {
+        return Err(BAD_DATE);
+    }
+
+    let mut secs = 0;
+    if let Some(time) = time {
+        let mut time_parts = time.splitn(3, ':');
+        let hour = next_number(&mut time_parts).ok_or(BAD_DATE)?;
+        let minute = next_number(&mut time_parts).ok_or(BAD_DATE)?;
+        let second = match time_parts.next() {
+            Some(s) => s.parse::<u64>().map_err(|_| BAD_DATE)?,
+            None => 0,
+        };
+        if hour > 23 || minute > 59 || second > 59 {
+            return Err(BAD_DATE);
+        }
+        secs = hour * 60 * 60 + minute * 60 + second;
+    }
+
+    let days = days_since_epoch(year, month, day);
+    Ok(UNIX_EPOCH + Duration::from_secs(days * 24 * 60 * 60 + secs))
+}
+
+// Writes the time as an ISO-8601 date and time in UTC, to the second, as
+// parse reads it ("2023-01-15T10:30:05Z"). None if it is before 1970.
+pub fn format(time: SystemTime) -> Option<String> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let mut days = secs / (24 * 60 * 60);
+    let mut year = 1970;
+    loop {
+        let year_days = if is_leap_year(year) { 366 } else { 365 };
+        if days < year_days {
+            break;
+        }
+        days -= year_days;
+        year += 1;
+    }
+    let mut month = 1;
+    while days >= days_in_month(year, month) {
+        days -= days_in_month(year, month);
+        month += 1;
+    }
+    let secs = secs % (24 * 60 * 60);
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        days + 1,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    ))
+}
+
+// The time of the date and (hour, minute, second) in UTC, or None if it
+// isn't a valid date from 1970 on.
+pub fn from_parts(year: u64, month: u64, day: u64, time: (u64, u64, u64)) -> Option<SystemTime> {
+    let (hour, minute, second) = time;
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    let days = days_since_epoch(year, month, day);
+    let secs = days * 24 * 60 * 60 + hour * 60 * 60 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn next_number<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Option<u64> {
+    parts.next()?.parse::<u64>().ok()
+}
+
+fn is_leap_year(year: u64) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u64, month: u64) -> u64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let mut days = 0;
+    for y in 1970..year {
+        days += if is_leap_year(y) { 366 } else { 365 };
+    }
+    for m in 1..month {
+        days += days_in_month(year, m);
+    }
+    days + day - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let now = SystemTime::now();
+        let expected = UNIX_EPOCH + Duration::from_secs(1_673_740_800);
+        assert_eq!(Ok(expected), parse("2023-01-15", now));
+        assert_eq!(
+            Ok(expected + Duration::from_secs(10 * 3600 + 30 * 60 + 5)),
+            parse("2023-01-15T10:30:05Z", now)
+        );
+        assert!(parse("2023-02-29", now).is_err());
+        assert!(parse("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_673_740_800 + 10 * 3600 + 30 * 60 + 5);
+        assert_eq!(Some("2023-01-15T10:30:05Z".to_string()), format(time));
+        let leap_day = parse("2024-02-29T23:59:59Z", time).unwrap();
+        assert_eq!(Some("2024-02-29T23:59:59Z".to_string()), format(leap_day));
+        assert_eq!(None, format(UNIX_EPOCH - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_673_740_800 + 10 * 3600 + 30 * 60 + 5);
+        assert_eq!(Some(time), from_parts(2023, 1, 15, (10, 30, 5)));
+        assert_eq!(None, from_parts(2023, 2, 29, (0, 0, 0)));
+        assert_eq!(None, from_parts(1969, 12, 31, (0, 0, 0)));
+    }
+
+    #[test]
+    fn test_parse_relative() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(
+            Ok(now - Duration::from_secs(7 * 24 * 60 * 60)),
+            parse("7d", now)
+        );
+        assert_eq!(Ok(now - Duration::from_secs(90)), parse("90s", now));
+    }
+}
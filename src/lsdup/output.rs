@@ -0,0 +1,1616 @@
+use crate::lsdup::action::ActionReport;
+use crate::lsdup::base64;
+use crate::lsdup::checksums::Checksums;
+use crate::lsdup::config::{Config, Format, PathEncoding};
+use crate::lsdup::devino::DevIno;
+use crate::lsdup::filevisitor::{AllInFileVisitor, Group};
+use crate::lsdup::hashing::{AdaptiveIo, ReadStrategy};
+use crate::lsdup::lenhash::LenHash;
+use crate::lsdup::mtime::{self, FileTimes};
+use crate::lsdup::prior::{self, PriorGroups};
+use console::style;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Writes the duplicate groups to out, and the summary to stderr.
+pub fn print_results(out: &mut dyn Write, dups: &AllInFileVisitor, config: &Config) {
+    let mut formatter = formatter(config);
+    let mut write_failed = false;
+    if config.manifest && !config.summary_only {
+        if let Err(e) = write_manifest(out, dups, config) {
+            eprintln!("Error: Could not write results: {}", e);
+            write_failed = true;
+        }
+    }
+    if config.size_histogram && !config.summary_only {
+        if let Err(e) = write_size_histogram(out, dups, config) {
+            eprintln!("Error: Could not write results: {}", e);
+            write_failed = true;
+        }
+    }
+    if let (Some(prior), false) = (&config.groups_from, config.summary_only) {
+        if let Err(e) = write_group_diff(out, dups, prior, config) {
+            eprintln!("Error: Could not write results: {}", e);
+            write_failed = true;
+        }
+    }
+    if let (Some(checksums), false) = (&config.match_manifest, config.summary_only) {
+        if let Err(e) = write_manifest_matches(out, dups, checksums, config) {
+            eprintln!("Error: Could not write results: {}", e);
+            write_failed = true;
+        }
+    }
+    let mut written = 0;
+    'groups: for (lenhash, paths) in dups {
+        if config.summary_only
+            || config.manifest
+            || config.groups_from.is_some()
+            || config.match_manifest.is_some()
+        {
+            break;
+        }
+        // With --same-name, each group is written as the sets of its files
+        // that have the same name.
+        let same_name;
+        let sets = if config.same_name {
+            same_name = same_name_sets(paths, config);
+            same_name.iter().map(Vec::as_slice).collect()
+        } else {
+            vec![paths.as_slice()]
+        };
+        for paths in sets {
+            if config.limit == Some(written) || (config.any && written == 1) {
+                break 'groups;
+            }
+            let group = dups.group(lenhash, paths);
+            if config.min_waste.is_some_and(|min| group.wasted < min) {
+                continue;
+            }
+            if let Err(e) = formatter.group(out, &group) {
+                eprintln!("Error: Could not write results: {}", e);
+                write_failed = true;
+                break 'groups;
+            }
+            written += 1;
+        }
+    }
+    if dups.interrupted() {
+        eprintln!("Interrupted, so this is a partial result of the files scanned so far.");
+    }
+    let totals = Totals {
+        files: dups.num_files(),
+        bytes: dups.total_file_bytes(),
+        duplicates: dups.num_duplicate_files(),
+        wasted_bytes: dups.wasted_bytes(),
+        groups: dups.into_iter().count(),
+    };
+    if !config.quiet {
+        print_summary(dups, config, &totals, written);
+    }
+
+    if !write_failed {
+        if let Err(e) = formatter.finish(out, &totals).and_then(|_| out.flush()) {
+            eprintln!("Error: Could not write results: {}", e);
+        }
+    }
+}
+
+// Prints the summary of what was found to stderr, with how many of the
+// groups were written.
+fn print_summary(dups: &AllInFileVisitor, config: &Config, totals: &Totals, written: usize) {
+    let (num_dups, dup_bytes, num_groups) = (totals.duplicates, totals.wasted_bytes, totals.groups);
+    eprintln!(
+        "{} files, {} analyzed.",
+        &dups.num_files(),
+        friendly_bytes(dups.total_file_bytes())
+    );
+    eprintln!(
+        "{} duplicate files, {} of duplicates.",
+        &num_dups,
+        friendly_bytes(dup_bytes)
+    );
+
+    eprintln!("{} sets of duplicates.", num_groups);
+    if let Some(min) = config.min_waste {
+        eprintln!(
+            "{} sets listed, those wasting at least {}.",
+            written,
+            friendly_bytes(min)
+        );
+    }
+    if let Some(percent) = config.sample {
+        print_sample_estimate(dups, percent);
+    }
+    if let Some((lenhash, paths)) = dups.largest_group() {
+        eprintln!(
+            "Largest set: {} copies of {}.",
+            paths.len(),
+            friendly_bytes(lenhash.len())
+        );
+    }
+    if config.flag_renamed {
+        let renamed = dups
+            .into_iter()
+            .filter(|(_, paths)| names_differ(paths))
+            .count();
+        eprintln!("{} sets have files with different names.", renamed);
+    }
+    if config.same_name {
+        let sets: usize = dups
+            .into_iter()
+            .map(|(_, paths)| same_name_sets(paths, config).len())
+            .sum();
+        eprintln!("{} sets of duplicates with the same name.", sets);
+    }
+    if config.no_hash {
+        eprintln!("Files were only counted, not hashed, so no duplicates were looked for.");
+    }
+    if dups.found_any() {
+        eprintln!("Stopped at the first set of duplicates found, so the rest weren't looked for.");
+    }
+    if config.size_histogram {
+        let (files, bytes) = dups
+            .size_counts()
+            .iter()
+            .filter(|(_, &count)| count > 1)
+            .fold((0, 0), |(files, bytes), (&size, &count)| {
+                (files + count as u64, bytes + size * count as u64)
+            });
+        eprintln!(
+            "{} files share their size with another, {} that a full scan would hash.",
+            files,
+            friendly_bytes(bytes)
+        );
+    }
+    if let Some(prefix_len) = config.quick {
+        eprintln!(
+            "Only the first {} bytes of files were compared, so these are likely but unverified duplicates.",
+            prefix_len
+        );
+    }
+    if let (Some(adaptive_io), true) = (dups.adaptive_io(), config.verbosity > 0) {
+        print_adaptive_io(adaptive_io);
+    }
+    if config.verbosity > 1 {
+        eprintln!(
+            "{} files hashed, {} files not hashed since no other file had the same size.",
+            dups.num_hashed(),
+            dups.num_unique_sizes()
+        );
+        if config.ends_check {
+            eprintln!(
+                "{} files not hashed whole since no other file had the same first and last blocks.",
+                dups.num_unique_ends()
+            );
+        }
+    }
+    if let Some(prior) = &config.groups_from {
+        let still = dups
+            .into_iter()
+            .filter(|(lenhash, _)| prior.contains(lenhash))
+            .count();
+        eprintln!(
+            "{} sets still duplicated, {} newly duplicated, {} resolved since the earlier result.",
+            still,
+            num_groups - still,
+            prior.len() - still
+        );
+    }
+    if let Some(checksums) = &config.match_manifest {
+        eprintln!(
+            "{} files match one of the {} files of the manifest, compared by {} hash.",
+            manifest_matches(dups, checksums).len(),
+            checksums.len(),
+            config.hashes[0].name()
+        );
+    }
+    if config.since.is_some() {
+        eprintln!(
+            "{} files not read again, unchanged since the earlier result.",
+            dups.num_reused()
+        );
+    }
+    if !dups.unreadable().is_empty() {
+        eprintln!(
+            "{} files could not be read, and were not compared.",
+            dups.unreadable().len()
+        );
+        if config.verbosity > 0 {
+            for (path, e) in dups.unreadable() {
+                eprintln!("\t{:?}: {}", path, e);
+            }
+        }
+    }
+    if !dups.unreadable_dirs().is_empty() {
+        eprintln!(
+            "{} directories could not be read, and were skipped.",
+            dups.unreadable_dirs().len()
+        );
+        if config.verbosity > 0 {
+            for (path, e) in dups.unreadable_dirs() {
+                eprintln!("\t{:?}: {}", path, e);
+            }
+        }
+    }
+    if dups.broken_symlinks() > 0 {
+        eprintln!("{} broken symlinks skipped.", dups.broken_symlinks());
+    }
+    let special_files = dups.special_files();
+    if special_files.total() > 0 {
+        eprintln!(
+            "{} special files skipped ({}).",
+            special_files.total(),
+            special_files
+        );
+    }
+    eprintln!(
+        "{} reclaimable, {:.1}% of the data analyzed.",
+        friendly_bytes(dup_bytes),
+        percent(dup_bytes, dups.total_file_bytes())
+    );
+    let secs = dups.elapsed().as_secs_f64();
+    if secs > 0.0 {
+        eprintln!(
+            "Scanned in {:.2}s, {}/s.",
+            secs,
+            friendly_bytes((dups.total_file_bytes() as f64 / secs) as u64)
+        );
+    }
+    if config.report_hardlink_savings {
+        print_hardlink_savings(dups, config);
+    }
+    if config.by_extension {
+        print_extension_report(dups);
+    }
+    if config.verbosity > 0 {
+        print_root_report(dups, config);
+    }
+}
+
+// Prints the totals for all files, estimated from those of the --sample by
+// scaling them up by the share of sizes that was sampled.
+fn print_sample_estimate(dups: &AllInFileVisitor, percent: f64) {
+    let scale = 100.0 / percent;
+    let estimate = |n: f64| (n * scale).round() as u64;
+    eprintln!(
+        "Estimated from a {}% sample of sizes: about {} files, {} analyzed, {} duplicate files, {} of duplicates.",
+        percent,
+        estimate(dups.num_files() as f64),
+        friendly_bytes(estimate(dups.total_file_bytes() as f64)),
+        estimate(dups.num_duplicate_files() as f64),
+        friendly_bytes(estimate(dups.wasted_bytes() as f64))
+    );
+}
+
+// The sets of files of the group with the same name, with at least
+// --min-count files.
+fn same_name_sets(paths: &[PathBuf], config: &Config) -> Vec<Vec<PathBuf>> {
+    let mut sets = same_name_groups(paths);
+    sets.retain(|set| set.len() >= config.min_count);
+    sets
+}
+
+// Prints what the action did, or with --dry-run would have done. With
+// --quiet, only the files that couldn't be changed are reported.
+pub fn print_action_report(report: &ActionReport, config: &Config) {
+    let outcome = config.action.map_or("shared", |action| action.outcome());
+    if !config.quiet {
+        eprintln!(
+            "{} duplicate files, {} {} {}.",
+            report.files,
+            friendly_bytes(report.bytes),
+            if config.dry_run { "would be" } else { "now" },
+            outcome
+        );
+    }
+    if report.failed > 0 {
+        eprintln!("{} duplicate files could not be changed.", report.failed);
+    }
+}
+
+// Tells how --adaptive-io chose to read the files, and how fast each way was
+// while it was choosing.
+fn print_adaptive_io(adaptive_io: &AdaptiveIo) {
+    let speed = |strategy| match adaptive_io.throughput(strategy) {
+        Some(bytes_per_sec) => format!("{}/s", friendly_bytes(bytes_per_sec as u64)),
+        None => "untried".to_string(),
+    };
+    let (mmap, buffered) = (speed(ReadStrategy::Mmap), speed(ReadStrategy::Buffered));
+    match adaptive_io.chosen() {
+        Some(ReadStrategy::Mmap) => eprintln!(
+            "Files were memory mapped, at {}, rather than read through a buffer, at {}.",
+            mmap, buffered
+        ),
+        Some(ReadStrategy::Buffered) => eprintln!(
+            "Files were read through a buffer, at {}, rather than memory mapped, at {}.",
+            buffered, mmap
+        ),
+        None => eprintln!(
+            "Too few files were read to choose how to read them: memory mapped at {}, \
+             through a buffer at {}.",
+            mmap, buffered
+        ),
+    }
+}
+
+// Reports the duplicate files and the bytes they waste for each extension,
+// most wasted first.
+fn print_extension_report(dups: &AllInFileVisitor) {
+    let report = wasted_by_extension(dups);
+    if report.is_empty() {
+        return;
+    }
+    eprintln!("{:<12} {:>10} {:>12}", "Extension", "Duplicates", "Wasted");
+    for (ext, files, bytes) in report {
+        eprintln!("{:<12} {:>10} {:>12}", ext, files, friendly_bytes(bytes));
+    }
+}
+
+// Prints the files, bytes, and duplicates found in each of the directories
+// that were scanned.
+fn print_root_report(dups: &AllInFileVisitor, config: &Config) {
+    eprintln!(
+        "{:>10} {:>12} {:>10}  Directory",
+        "Files", "Size", "Duplicates"
+    );
+    for (dir, totals) in config.dirs.iter().zip(dups.root_totals()) {
+        eprintln!(
+            "{:>10} {:>12} {:>10}  {}",
+            totals.files,
+            friendly_bytes(totals.bytes),
+            totals.duplicates,
+            dir.display()
+        );
+    }
+}
+
+// The number of duplicate files and their bytes for each lowercased extension,
+// or "(none)" for files without one, sorted by most bytes. The first file of
+// each group isn't a duplicate, so it isn't counted.
+pub fn wasted_by_extension(dups: &AllInFileVisitor) -> Vec<(String, usize, u64)> {
+    let mut by_ext: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    for (lenhash, paths) in dups {
+        for path in &paths[1..] {
+            let ext = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_else(|| "(none)".to_string());
+            let entry = by_ext.entry(ext).or_default();
+            entry.0 += 1;
+            entry.1 += lenhash.len();
+        }
+    }
+    let mut report: Vec<_> = by_ext
+        .into_iter()
+        .map(|(ext, (files, bytes))| (ext, files, bytes))
+        .collect();
+    report.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    report
+}
+
+// Reports how much space hardlinking the duplicates would save, without
+// changing anything.
+fn print_hardlink_savings(dups: &AllInFileVisitor, config: &Config) {
+    let mut savings = 0;
+    for (lenhash, paths) in dups {
+        let group_savings = hardlink_savings(lenhash, paths);
+        if config.verbosity > 0 {
+            eprintln!(
+                "{} would be saved by hardlinking to {:?}.",
+                friendly_bytes(group_savings),
+                paths[0]
+            );
+        }
+        savings += group_savings;
+    }
+    eprintln!(
+        "{} would be saved by hardlinking, beyond {} already shared by hardlinks.",
+        friendly_bytes(savings),
+        friendly_bytes(dups.linked_bytes())
+    );
+}
+
+// Bytes that would be freed by hardlinking the files of the group together.
+// Files can only be hardlinked to others on the same device, so there is
+// still one copy per device, and entries within archives can't be hardlinked
+// at all. Files already hardlinked to each other share one copy, so they
+// don't count again.
+pub fn hardlink_savings(lenhash: &LenHash, paths: &[PathBuf]) -> u64 {
+    let mut devices = BTreeSet::new();
+    let mut inodes = BTreeSet::new();
+    for path in paths {
+        if let Some(devino) = DevIno::from_path(path) {
+            devices.insert(devino.dev());
+            inodes.insert(devino);
+        }
+    }
+    (inodes.len() - devices.len()) as u64 * lenhash.len()
+}
+
+// The part as a percentage of the whole, or 0 if the whole is 0.
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / whole as f64
+}
+
+// Writes the duplicate groups in one of the --format formats. Each group is
+// written as soon as it is given, and anything that needs all of them, such
+// as a summary of the totals, is written by finish.
+pub trait Formatter {
+    // Writes one group of identical files, the original first.
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()>;
+
+    // Writes whatever comes after the last group.
+    fn finish(&mut self, _out: &mut dyn Write, _totals: &Totals) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// What was found over the whole scan.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct Totals {
+    pub files: u32,
+    pub bytes: u64,
+    pub duplicates: usize,
+    pub wasted_bytes: u64,
+    pub groups: usize,
+}
+
+// The formatter for the configured --format.
+pub fn formatter(config: &Config) -> Box<dyn Formatter + '_> {
+    match config.format {
+        Format::Human => Box::new(Human { config, groups: 0 }),
+        Format::Ndjson => Box::new(Ndjson { config }),
+        Format::Fdupes => Box::new(Fdupes { config }),
+        Format::Sqlite => Box::new(Sqlite),
+        Format::NullGroups => Box::new(NullGroups { config }),
+        Format::Table => Box::new(Table {
+            config,
+            width: table_width(config),
+            groups: 0,
+        }),
+    }
+}
+
+// Size and hash header, followed by one path per line, for each group.
+// Groups are separated by a blank line, or by the --group-separator line.
+pub struct Human<'a> {
+    pub config: &'a Config,
+    // Number of groups written so far.
+    pub groups: usize,
+}
+
+impl<'a> Formatter for Human<'a> {
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        let config = self.config;
+        match &config.group_separator {
+            Some(separator) if self.groups > 0 => writeln!(out, "{}", separator)?,
+            Some(_) => (),
+            None => writeln!(out)?,
+        }
+        self.groups += 1;
+        if !config.no_header {
+            self.header(out, group)?;
+        }
+        for (i, path) in listed(group.paths, config).iter().enumerate() {
+            if config.mark_original {
+                write!(out, "{}", if i == 0 { "* " } else { "  " })?;
+            }
+            if config.show_inode {
+                match DevIno::from_path(path) {
+                    Some(devino) => write!(out, "{} ", devino)?,
+                    None => write!(out, "- ")?,
+                }
+            }
+            // The original is bold, and its duplicates dim.
+            let shown = output_path(path, config);
+            let shown = style(shown.to_string_lossy()).force_styling(config.color);
+            let shown = if i == 0 { shown.bold() } else { shown.dim() };
+            write!(out, "{}", shown)?;
+            if config.show_times {
+                let times = group.times.get(i).copied().unwrap_or_default();
+                write!(out, "  ({})", file_times(&times))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Human<'a> {
+    // Writes the size, wasted space, and hash of the group on one line.
+    fn header(&self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        let config = self.config;
+        let (lenhash, paths) = (group.lenhash, group.paths);
+        let mut header = format!(
+            "Size: {}  Wasted: {}  ",
+            friendly_bytes(lenhash.len()),
+            friendly_bytes(group.wasted)
+        );
+        match (lenhash.extra_hex(), &config.hashes[..]) {
+            // With two algorithms, each hash is labeled with its own.
+            (Some(extra), [first, second]) => header.push_str(&format!(
+                "{}: {}  {}: {}",
+                first.name(),
+                lenhash.to_hex(),
+                second.name(),
+                extra
+            )),
+            _ => header.push_str(&format!("Hash: {}", lenhash.to_hex())),
+        }
+        if let Some(prefix_len) = config.quick {
+            header.push_str(&format!("  (unverified, first {} bytes)", prefix_len));
+        }
+        if config.flag_renamed && names_differ(paths) {
+            header.push_str("  (renamed)");
+        }
+        writeln!(out, "{}", style(header).cyan().force_styling(config.color))
+    }
+}
+
+// One JSON object per group, per line, then a last line with the totals.
+pub struct Ndjson<'a> {
+    pub config: &'a Config,
+}
+
+impl<'a> Formatter for Ndjson<'a> {
+    // Writes the group as one line of JSON, and flushes it so that whatever is
+    // reading the output can handle each group as soon as it is written.
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        let (lenhash, paths) = (group.lenhash, group.paths);
+        let config = self.config;
+        write!(
+            out,
+            "{{\"size\":{},\"hash\":\"{}\",",
+            lenhash.len(),
+            lenhash.to_hex()
+        )?;
+        if let (Some(extra), [first, second]) = (lenhash.extra_hex(), &config.hashes[..]) {
+            write!(
+                out,
+                "\"hashes\":{{\"{}\":\"{}\",\"{}\":\"{}\"}},",
+                first.name(),
+                lenhash.to_hex(),
+                second.name(),
+                extra
+            )?;
+        }
+        if config.quick.is_some() {
+            write!(out, "\"verified\":false,")?;
+        }
+        if config.flag_renamed {
+            write!(out, "\"renamed\":{},", names_differ(paths))?;
+        }
+        if config.path_encoding == PathEncoding::Base64 {
+            write!(out, "\"path_encoding\":\"base64\",")?;
+        }
+        let paths = listed(paths, config);
+        write!(out, "\"paths\":[")?;
+        for (i, path) in paths.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let path = output_path(path, config);
+            let path = match config.path_encoding {
+                PathEncoding::Base64 => json_string(&base64::encode(&path_bytes(&path))),
+                _ => json_string(&path.to_string_lossy()),
+            };
+            write!(out, "{}", path)?;
+        }
+        // The modification time of each file is written too, so that --since can
+        // tell which files changed after this result was written. With
+        // --show-times, the times read during the scan are written instead.
+        let times = |i: usize| group.times.get(i).copied().unwrap_or_default();
+        write!(out, "],\"modified\":[")?;
+        for (i, path) in paths.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let nanos = if config.show_times {
+                times(i).modified.and_then(prior::nanos)
+            } else {
+                prior::modified_nanos(path)
+            };
+            match nanos {
+                Some(nanos) => write!(out, "{}", nanos)?,
+                None => write!(out, "null")?,
+            }
+        }
+        if config.show_times {
+            write!(out, "],\"changed\":[")?;
+            for i in 0..paths.len() {
+                if i > 0 {
+                    write!(out, ",")?;
+                }
+                match times(i).changed.and_then(prior::nanos) {
+                    Some(nanos) => write!(out, "{}", nanos)?,
+                    None => write!(out, "null")?,
+                }
+            }
+        }
+        writeln!(out, "]}}")?;
+        out.flush()
+    }
+
+    // The summary is a line of its own, rather than a wrapper around the
+    // groups, so that the groups can still be read as they are written.
+    fn finish(&mut self, out: &mut dyn Write, totals: &Totals) -> io::Result<()> {
+        writeln!(
+            out,
+            "{{\"summary\":{{\"files\":{},\"bytes\":{},\"duplicates\":{},\"wasted_bytes\":{},\"groups\":{}}}}}",
+            totals.files, totals.bytes, totals.duplicates, totals.wasted_bytes, totals.groups
+        )
+    }
+}
+
+// One path per line, with a blank line after each group, like fdupes.
+pub struct Fdupes<'a> {
+    pub config: &'a Config,
+}
+
+impl<'a> Formatter for Fdupes<'a> {
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        for path in listed(group.paths, self.config) {
+            write_encoded_path(out, &output_path(path, self.config), self.config)?;
+            writeln!(out)?;
+        }
+        // With only the original, each group is already just one line.
+        if self.config.originals_only {
+            return Ok(());
+        }
+        writeln!(out)
+    }
+}
+
+// Each path followed by a NUL, and another NUL after each group, so a group
+// ends at two NULs in a row. Paths can't have a NUL in them, nor be empty, so
+// this holds whatever the file names are, even with newlines in them.
+pub struct NullGroups<'a> {
+    pub config: &'a Config,
+}
+
+impl<'a> Formatter for NullGroups<'a> {
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        for path in listed(group.paths, self.config) {
+            out.write_all(&path_bytes(&output_path(path, self.config)))?;
+            out.write_all(b"\0")?;
+        }
+        // With only the original, each group is already just one path.
+        if self.config.originals_only {
+            return Ok(());
+        }
+        out.write_all(b"\0")
+    }
+}
+
+// Writes the path as --path-encoding says, for the line-based formats.
+fn write_encoded_path(out: &mut dyn Write, path: &Path, config: &Config) -> io::Result<()> {
+    match config.path_encoding {
+        PathEncoding::Lossy => write!(out, "{}", path.to_string_lossy()),
+        PathEncoding::Raw => out.write_all(&path_bytes(path)),
+        PathEncoding::Base64 => write!(out, "{}", base64::encode(&path_bytes(path))),
+    }
+}
+
+// The path as it is, on Unix where paths are any bytes. Elsewhere, paths
+// that aren't valid Unicode are written lossily.
+#[cfg(target_family = "unix")]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+// The path with the bytes written by path_bytes.
+#[cfg(target_family = "unix")]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// One line per group, in aligned columns: the size of each file, how many
+// there are, the start of the hash, and the path of the original. When
+// writing to a terminal, the path is cut to fit its width.
+pub struct Table<'a> {
+    pub config: &'a Config,
+    // Columns of the terminal being written to, if any.
+    pub width: Option<usize>,
+    // Number of groups written so far.
+    pub groups: usize,
+}
+
+// How many hex digits of the hash are shown, enough to tell groups apart.
+const TABLE_HASH_LEN: usize = 12;
+
+impl<'a> Formatter for Table<'a> {
+    fn group(&mut self, out: &mut dyn Write, group: &Group) -> io::Result<()> {
+        let (lenhash, paths) = (group.lenhash, group.paths);
+        let config = self.config;
+        if self.groups == 0 && !config.no_header {
+            let header = format!(
+                "{:>10}  {:>5}  {:<hash_len$}  PATH",
+                "SIZE",
+                "COUNT",
+                "HASH",
+                hash_len = TABLE_HASH_LEN
+            );
+            writeln!(out, "{}", style(header).cyan().force_styling(config.color))?;
+        }
+        self.groups += 1;
+        let row = format!(
+            "{:>10}  {:>5}  {}  ",
+            friendly_bytes(lenhash.len()),
+            paths.len(),
+            &lenhash.to_hex()[..TABLE_HASH_LEN]
+        );
+        let path = output_path(&paths[0], config);
+        let path = path.to_string_lossy();
+        let path = match self.width {
+            Some(width) => cut_to_width(&path, width.saturating_sub(row.len())),
+            None => &path,
+        };
+        writeln!(out, "{}{}", row, path)
+    }
+}
+
+// The width of the terminal the table is written to, or None when writing to
+// a file or pipe, where long paths are left whole.
+fn table_width(config: &Config) -> Option<usize> {
+    let term = console::Term::stdout();
+    if config.output.is_some() || !term.is_term() {
+        return None;
+    }
+    term.size_checked().map(|(_rows, columns)| columns as usize)
+}
+
+// The start of the text that fits within the width of a terminal, leaving a
+// few columns spare. Widths are counted in bytes, so text that isn't ASCII
+// may be cut shorter than it needs to be.
+pub fn cut_to_width(text: &str, width: usize) -> &str {
+    if width > 4 && text.len() >= width - 3 {
+        for i in (0..(width - 3)).rev() {
+            if text.is_char_boundary(i) {
+                return &text[..i];
+            }
+        }
+    }
+    text
+}
+
+// Nothing, since the sqlite catalog is written separately once the scan is
+// done, not as the groups are printed.
+pub struct Sqlite;
+
+impl Formatter for Sqlite {
+    fn group(&mut self, _out: &mut dyn Write, _group: &Group) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The files of the group that are listed: all of them, or with
+// --originals-only, just the original.
+fn listed<'p>(paths: &'p [PathBuf], config: &Config) -> &'p [PathBuf] {
+    if config.originals_only {
+        &paths[..1]
+    } else {
+        paths
+    }
+}
+
+// Writes "HASH SIZE PATH" for every file that was hashed, ordered by path.
+pub fn write_manifest(
+    out: &mut dyn Write,
+    dups: &AllInFileVisitor,
+    config: &Config,
+) -> io::Result<()> {
+    let mut files: Vec<_> = dups
+        .all_groups()
+        .flat_map(|(lenhash, paths)| paths.iter().map(move |path| (path, lenhash)))
+        .collect();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+    for (path, lenhash) in files {
+        write!(out, "{} {} ", lenhash.to_hex(), lenhash.len())?;
+        write_encoded_path(out, &output_path(path, config), config)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+// Writes each scanned file with the same contents as a file of the
+// --match-manifest manifest, ordered by path, followed by the files of the
+// manifest it matches, indented.
+pub fn write_manifest_matches(
+    out: &mut dyn Write,
+    dups: &AllInFileVisitor,
+    checksums: &Checksums,
+    config: &Config,
+) -> io::Result<()> {
+    for (path, known) in manifest_matches(dups, checksums) {
+        write_encoded_path(out, &output_path(path, config), config)?;
+        writeln!(out)?;
+        for known in known {
+            write!(out, "\t")?;
+            write_encoded_path(out, known, config)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+// The scanned files with the same hash as a file of the manifest, ordered by
+// path, and the files of the manifest they match.
+pub fn manifest_matches<'a>(
+    dups: &'a AllInFileVisitor,
+    checksums: &'a Checksums,
+) -> Vec<(&'a PathBuf, &'a [PathBuf])> {
+    let mut matches: Vec<_> = dups
+        .all_groups()
+        .filter_map(|(lenhash, paths)| Some((paths, checksums.matching(lenhash)?)))
+        .flat_map(|(paths, known)| paths.iter().map(move |path| (path, known)))
+        .collect();
+    matches.sort_by(|a, b| a.0.cmp(b.0));
+    matches
+}
+
+// Writes how many files were found in each range of sizes, from one power of
+// two up to the next, and how many of those share their size with another
+// file, for --size-histogram. Ranges without any files are left out.
+pub fn write_size_histogram(
+    out: &mut dyn Write,
+    dups: &AllInFileVisitor,
+    config: &Config,
+) -> io::Result<()> {
+    let mut ranges: BTreeMap<Option<u32>, (u64, u64)> = BTreeMap::new();
+    for (&size, &count) in dups.size_counts() {
+        // Empty files are a range of their own, the rest by their highest bit.
+        let range = size.checked_ilog2();
+        let (files, same_size) = ranges.entry(range).or_default();
+        *files += count as u64;
+        if count > 1 {
+            *same_size += count as u64;
+        }
+    }
+    if !config.no_header {
+        writeln!(out, "{:>21}  {:>8}  {:>9}", "SIZE", "FILES", "SAME SIZE")?;
+    }
+    for (range, (files, same_size)) in ranges {
+        let range = match range {
+            Some(bit) => format!(
+                "{} - {}",
+                friendly_bytes(1 << bit),
+                friendly_bytes(1 << (bit + 1))
+            ),
+            None => friendly_bytes(0),
+        };
+        writeln!(out, "{:>21}  {:>8}  {:>9}", range, files, same_size)?;
+    }
+    Ok(())
+}
+
+// Writes the groups that are still duplicated since the earlier --groups-from
+// result, then those newly duplicated, then those resolved, each under its
+// own heading. Resolved groups are listed with the files they had then.
+pub fn write_group_diff(
+    out: &mut dyn Write,
+    dups: &AllInFileVisitor,
+    prior: &PriorGroups,
+    config: &Config,
+) -> io::Result<()> {
+    for (heading, still) in &[("Still duplicated:", true), ("Newly duplicated:", false)] {
+        writeln!(out, "{}", style(heading).bold().force_styling(config.color))?;
+        let mut formatter = Human { config, groups: 0 };
+        for (lenhash, paths) in dups {
+            if prior.contains(lenhash) == *still {
+                formatter.group(out, &dups.group(lenhash, paths))?;
+            }
+        }
+        writeln!(out)?;
+    }
+    writeln!(
+        out,
+        "{}",
+        style("Resolved:").bold().force_styling(config.color)
+    )?;
+    let current: HashSet<LenHash> = dups
+        .into_iter()
+        .map(|(lenhash, _)| lenhash.without_extra())
+        .collect();
+    for (lenhash, paths) in prior.iter() {
+        if current.contains(lenhash) {
+            continue;
+        }
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{}",
+            style(format!(
+                "Size: {}  Hash: {}",
+                friendly_bytes(lenhash.len()),
+                lenhash.to_hex()
+            ))
+            .cyan()
+            .force_styling(config.color)
+        )?;
+        for path in paths {
+            writeln!(out, "{}", path.to_string_lossy())?;
+        }
+    }
+    Ok(())
+}
+
+// True if the files of the group don't all have the same name, regardless of
+// the directories they are in.
+pub fn names_differ(paths: &[PathBuf]) -> bool {
+    let mut names = paths.iter().map(|path| path.file_name());
+    match names.next() {
+        Some(first) => names.any(|name| name != first),
+        None => false,
+    }
+}
+
+// Splits the group into the files that have the same name, once a copy
+// suffix is taken off, keeping the order of the group so the original of
+// each comes first. Files without any others of the same name are left out.
+pub fn same_name_groups(paths: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut groups: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    for path in paths {
+        let name = copy_base_name(path);
+        match groups.iter_mut().find(|(other, _)| *other == name) {
+            Some((_, group)) => group.push(path.clone()),
+            None => groups.push((name, vec![path.clone()])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, group)| group)
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+// The file name without a " (N)" copy suffix just before the extension, or at
+// the end if there is none, as added by browsers and file managers: both
+// "report (1).pdf" and "report.pdf" are "report.pdf". Only digits in the
+// parentheses are taken as a copy number.
+fn copy_base_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name.as_str(), ""),
+    };
+    let stripped = stem.strip_suffix(')').and_then(|rest| {
+        let open = rest.rfind(" (")?;
+        let digits = &rest[open + 2..];
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            Some(&rest[..open])
+        } else {
+            None
+        }
+    });
+    match stripped {
+        Some(stem) => format!("{}{}", stem, ext),
+        None => name,
+    }
+}
+
+// The path as it should be printed. With --absolute, this is the canonical
+// path, and with --relative-to, the canonical path within that directory,
+// unless it can't be resolved (such as if the file was removed after it was
+// scanned), in which case the path is printed as it was found.
+fn output_path<'a>(path: &'a Path, config: &Config) -> Cow<'a, Path> {
+    if let Some(base) = &config.relative_to {
+        match fs::canonicalize(path) {
+            Ok(absolute) => {
+                if let Ok(relative) = absolute.strip_prefix(base) {
+                    return Cow::Owned(relative.to_path_buf());
+                }
+                eprintln!(
+                    "Note: {:?} is not within {:?}, so it is printed in full.",
+                    absolute, base
+                );
+                return Cow::Owned(absolute);
+            }
+            Err(e) => eprintln!("Warning: Could not make {:?} relative: {}", path, e),
+        }
+    }
+    if config.absolute {
+        match fs::canonicalize(path) {
+            Ok(absolute) => return Cow::Owned(absolute),
+            Err(e) => eprintln!("Warning: Could not make {:?} absolute: {}", path, e),
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+// When the file was last modified and its status last changed, in UTC, for
+// --show-times. A time that wasn't read is a -.
+fn file_times(times: &FileTimes) -> String {
+    let time = |time: Option<SystemTime>| time.and_then(mtime::format);
+    format!(
+        "modified {}, changed {}",
+        time(times.modified).as_deref().unwrap_or("-"),
+        time(times.changed).as_deref().unwrap_or("-")
+    )
+}
+
+// Quotes and escapes the value as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn friendly_bytes(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        let value = (bytes as f64) / (1024 * 1024 * 1024) as f64;
+        return format!("{:.1} GB", value);
+    } else if bytes >= 1 << 20 {
+        let value = (bytes as f64) / (1024 * 1024) as f64;
+        return format!("{:.1} MB", value);
+    } else if bytes >= 1 << 10 {
+        let value = (bytes as f64) / 1024.0;
+        return format!("{:.1} kB", value);
+    }
+    format!("{} B", bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsdup::hashing::HashAlgorithm;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Writes the group with the formatter, and returns what was written.
+    fn write_group(formatter: &mut dyn Formatter, lenhash: &LenHash, paths: &[PathBuf]) -> String {
+        let config = Config::default();
+        let mut out = Vec::new();
+        formatter
+            .group(
+                &mut out,
+                &AllInFileVisitor::new(&config).group(lenhash, paths),
+            )
+            .expect("Could not write group.");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_ndjson_group() {
+        // Given a group of two files, one with characters that need escaping in JSON,
+        let lenhash = LenHash::from(10, [0xab; 32]);
+        let paths = vec![
+            PathBuf::from("dir/a.txt"),
+            PathBuf::from("dir/\"b\"\\\n.txt"),
+        ];
+
+        // When the group is written as ndjson,
+        let config = Config::default();
+        let out = write_group(&mut Ndjson { config: &config }, &lenhash, &paths);
+
+        // Then it is a single line of JSON with the size, hash, and escaped paths,
+        // and no modification times since the files don't exist.
+        let expected = format!(
+            "{{\"size\":10,\"hash\":\"{}\",\"paths\":[\"dir/a.txt\",\"dir/\\\"b\\\"\\\\\\n.txt\"],\"modified\":[null,null]}}\n",
+            "ab".repeat(32)
+        );
+        assert_eq!(expected, out);
+    }
+
+    #[test]
+    fn test_ndjson_finish() {
+        // Given the totals of a scan,
+        let totals = Totals {
+            files: 3,
+            bytes: 30,
+            duplicates: 2,
+            wasted_bytes: 20,
+            groups: 1,
+        };
+
+        // When the end of the ndjson output is written,
+        let config = Config::default();
+        let mut out = Vec::new();
+        Ndjson { config: &config }
+            .finish(&mut out, &totals)
+            .expect("Could not write summary.");
+
+        // Then it is a line with the summary object.
+        assert_eq!(
+            "{\"summary\":{\"files\":3,\"bytes\":30,\"duplicates\":2,\"wasted_bytes\":20,\"groups\":1}}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_human_group() {
+        // Given a group of three 2 kB files,
+        let lenhash = LenHash::from(2048, [0xcd; 32]);
+        let paths = vec![
+            PathBuf::from("a.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+        ];
+
+        // When the group is written for people to read,
+        let config = Config::default();
+        let out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+
+        // Then the header has the space wasted by the two duplicates.
+        let expected = format!(
+            "\nSize: 2.0 kB  Wasted: 4.0 kB  Hash: {}\na.txt\nb.txt\nc.txt\n",
+            "cd".repeat(32)
+        );
+        assert_eq!(expected, out);
+
+        // and with color, the same text is wrapped in escape codes.
+        let config = Config {
+            color: true,
+            ..Default::default()
+        };
+        let out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+        assert!(out.contains("\u{1b}["));
+        assert_eq!(expected, console::strip_ansi_codes(&out));
+
+        // and with the original marked, it is starred and the rest line up with it.
+        let config = Config {
+            mark_original: true,
+            ..Default::default()
+        };
+        let out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+        assert!(out.ends_with("\n* a.txt\n  b.txt\n  c.txt\n"));
+
+        // and without the header, only the paths remain after the blank line.
+        let config = Config {
+            no_header: true,
+            ..Default::default()
+        };
+        let out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+        assert_eq!("\na.txt\nb.txt\nc.txt\n", out);
+    }
+
+    #[test]
+    fn test_two_hashes() {
+        // Given a group hashed with two algorithms,
+        let lenhash = LenHash::with_extra(10, [0x78; 32], [0x9a; 32]);
+        let paths = vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")];
+        let config = Config {
+            hashes: vec![HashAlgorithm::Blake3, HashAlgorithm::Sha256],
+            ..Default::default()
+        };
+
+        // When it is written for people to read, and as ndjson,
+        let human = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+        let ndjson = write_group(&mut Ndjson { config: &config }, &lenhash, &paths);
+
+        // Then both hashes are listed, each with its algorithm.
+        assert!(human.contains(&format!(
+            "blake3: {}  sha256: {}\n",
+            "78".repeat(32),
+            "9a".repeat(32)
+        )));
+        assert!(ndjson.contains(&format!(
+            "\"hashes\":{{\"blake3\":\"{}\",\"sha256\":\"{}\"}},",
+            "78".repeat(32),
+            "9a".repeat(32)
+        )));
+    }
+
+    #[test]
+    fn test_human_group_separator() {
+        // Given two groups, and a separator to write between groups,
+        let lenhash = LenHash::from(10, [0x34; 32]);
+        let first = vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")];
+        let second = vec![PathBuf::from("c.txt"), PathBuf::from("d.txt")];
+        let config = Config {
+            group_separator: Some("---".to_string()),
+            ..Default::default()
+        };
+
+        // When both groups are written for people to read,
+        let mut human = Human {
+            config: &config,
+            groups: 0,
+        };
+        let out =
+            write_group(&mut human, &lenhash, &first) + &write_group(&mut human, &lenhash, &second);
+
+        // Then the separator is only between them, instead of blank lines.
+        let header = format!("Size: 10 B  Wasted: 10 B  Hash: {}", "34".repeat(32));
+        assert_eq!(
+            format!("{0}\na.txt\nb.txt\n---\n{0}\nc.txt\nd.txt\n", header),
+            out
+        );
+    }
+
+    #[test]
+    fn test_flag_renamed() {
+        // Given a group of files with the same name, and one with different names,
+        let lenhash = LenHash::from(10, [0xef; 32]);
+        let same = vec![PathBuf::from("a/x.txt"), PathBuf::from("b/x.txt")];
+        let renamed = vec![PathBuf::from("a/x.txt"), PathBuf::from("a/y.txt")];
+        let config = Config {
+            flag_renamed: true,
+            ..Default::default()
+        };
+
+        // When the groups are written for people to read,
+        let same_out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &same,
+        );
+        let renamed_out = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &renamed,
+        );
+
+        // Then only the group with different names is marked.
+        assert!(!same_out.contains("(renamed)"));
+        assert!(renamed_out.contains("(renamed)"));
+
+        // and as ndjson, the group says that it was.
+        let out = write_group(&mut Ndjson { config: &config }, &lenhash, &renamed);
+        assert!(out.contains("\"renamed\":true,"));
+    }
+
+    #[test]
+    fn test_same_name_groups() {
+        // Given a group with copies of two files, one named as a browser would,
+        let paths = vec![
+            PathBuf::from("a/report.pdf"),
+            PathBuf::from("b/notes.txt"),
+            PathBuf::from("b/report (1).pdf"),
+            PathBuf::from("c/report (2).pdf"),
+            PathBuf::from("c/notes"),
+            PathBuf::from("c/notes (copy).txt"),
+        ];
+
+        // When split by name,
+        let groups = same_name_groups(&paths);
+
+        // Then only the copies of the report are together, the original first,
+        // since the others have no copy of the same name.
+        assert_eq!(
+            vec![vec![
+                PathBuf::from("a/report.pdf"),
+                PathBuf::from("b/report (1).pdf"),
+                PathBuf::from("c/report (2).pdf"),
+            ]],
+            groups
+        );
+        assert_eq!("archive", copy_base_name(Path::new("archive (3)")));
+        assert_eq!(".bashrc", copy_base_name(Path::new(".bashrc")));
+    }
+
+    #[test]
+    fn test_fdupes_group() {
+        // Given a group of two files,
+        let lenhash = LenHash::from(10, [0x12; 32]);
+        let paths = vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir/b.txt")];
+
+        // When the group is written in the fdupes format,
+        let config = Config::default();
+        let out = write_group(&mut Fdupes { config: &config }, &lenhash, &paths);
+
+        // Then each path is on its own line, followed by a blank line.
+        assert_eq!("dir/a.txt\ndir/b.txt\n\n", out);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_path_encoding() {
+        // Given a group with a path that isn't valid UTF-8,
+        let lenhash = LenHash::from(10, [0x34; 32]);
+        let paths = vec![
+            PathBuf::from("a.txt"),
+            path_from_bytes(b"b\xff.txt".to_vec()),
+        ];
+
+        // When the group is written in fdupes format with each path encoding,
+        let mut config = Config::default();
+        let mut written = Vec::new();
+        for encoding in [PathEncoding::Lossy, PathEncoding::Raw, PathEncoding::Base64] {
+            config.path_encoding = encoding;
+            let group = AllInFileVisitor::new(&config).group(&lenhash, &paths);
+            let mut out = Vec::new();
+            Fdupes { config: &config }
+                .group(&mut out, &group)
+                .expect("Could not write group.");
+            written.push(out);
+        }
+
+        // Then lossy replaces the bad byte, raw writes it as it is, and base64
+        // encodes every path.
+        assert_eq!(b"a.txt\nb\xef\xbf\xbd.txt\n\n".to_vec(), written[0]);
+        assert_eq!(b"a.txt\nb\xff.txt\n\n".to_vec(), written[1]);
+        assert_eq!(b"YS50eHQ=\nYv8udHh0\n\n".to_vec(), written[2]);
+
+        // And when written as ndjson with base64, the group says so, and the
+        // path reads back as it was.
+        let out = write_group(&mut Ndjson { config: &config }, &lenhash, &paths);
+        assert!(out.contains("\"path_encoding\":\"base64\",\"paths\":[\"YS50eHQ=\",\"Yv8udHh0\"]"));
+        let prior = prior::parse_groups(&out).expect("Could not parse result.");
+        let (_, read_back) = prior.iter().next().unwrap();
+        assert_eq!(&paths, read_back);
+    }
+
+    #[test]
+    fn test_show_times() {
+        // Given a file modified at a known time, and a file that is gone,
+        let target_dir = Path::new("./target/test_dir/show_times");
+        let _ = fs::remove_dir_all(target_dir);
+        fs::create_dir_all(target_dir).unwrap();
+        let file = target_dir.join("a.txt");
+        let set_modified = |secs| {
+            fs::OpenOptions::new()
+                .append(true)
+                .open(&file)
+                .and_then(|opened| opened.set_modified(UNIX_EPOCH + Duration::from_secs(secs)))
+                .expect("Could not set the modification time.");
+        };
+        fs::write(&file, b"Contents for a test of times. zxcvbn").expect("Could not create file.");
+        set_modified(1_673_778_605);
+        let lenhash = LenHash::from(10, [0x78; 32]);
+        let paths = vec![file.clone(), target_dir.join("gone.txt")];
+
+        // and the files were scanned with their times,
+        let config = Config {
+            show_times: true,
+            no_header: true,
+            ..Default::default()
+        };
+        let mut dups = AllInFileVisitor::new(&config);
+        dups.visit_path(file.clone());
+        dups.finish();
+
+        // When the file is modified again after the scan, and the group is
+        // written,
+        set_modified(1_700_000_000);
+        let group = dups.group(&lenhash, &paths);
+        let mut written = Vec::new();
+        for formatter in &mut [
+            &mut Human {
+                config: &config,
+                groups: 0,
+            } as &mut dyn Formatter,
+            &mut Ndjson { config: &config },
+        ] {
+            let mut out = Vec::new();
+            formatter
+                .group(&mut out, &group)
+                .expect("Could not write group.");
+            written.push(String::from_utf8(out).unwrap());
+        }
+        let (human, ndjson) = (&written[0], &written[1]);
+
+        // Then each path is followed by its times as they were when it was
+        // scanned, or - for those that weren't,
+        let lines: Vec<&str> = human.lines().collect();
+        assert!(
+            lines[1].starts_with(&format!(
+                "{}  (modified 2023-01-15T10:30:05Z, changed ",
+                file.display()
+            )),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[2].ends_with("gone.txt  (modified -, changed -)"));
+
+        // and ndjson has when each changed, next to when each was modified.
+        assert!(
+            ndjson.contains("\"modified\":[1673778605000000000,null],\"changed\":["),
+            "{}",
+            ndjson
+        );
+        assert!(ndjson.ends_with(",null]}\n"), "{}", ndjson);
+    }
+
+    #[test]
+    fn test_null_groups_group() {
+        // Given a group of two files, one with a newline in its name,
+        let lenhash = LenHash::from(10, [0x56; 32]);
+        let paths = vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir/b\n.txt")];
+
+        // When the group is written with NUL separators,
+        let config = Config::default();
+        let out = write_group(&mut NullGroups { config: &config }, &lenhash, &paths);
+
+        // Then each path ends with a NUL, and the group with another.
+        assert_eq!("dir/a.txt\0dir/b\n.txt\0\0", out);
+    }
+
+    #[test]
+    fn test_output_path_relative_to() {
+        // Given a file within a base directory, and a file outside of it,
+        let target_dir = Path::new("./target/test_dir/relative_to");
+        let _ = fs::remove_dir_all(target_dir);
+        let base = target_dir.join("base");
+        fs::create_dir_all(base.join("sub")).unwrap();
+        let inside = base.join("sub").join("a.txt");
+        let outside = target_dir.join("b.txt");
+        fs::write(&inside, b"inside").unwrap();
+        fs::write(&outside, b"outside").unwrap();
+
+        // and the configuration is to print paths relative to the base,
+        let config = Config {
+            relative_to: Some(fs::canonicalize(&base).unwrap()),
+            ..Default::default()
+        };
+
+        // Then the file within it is printed relative to it,
+        assert_eq!(Path::new("sub/a.txt"), output_path(&inside, &config));
+
+        // and the file outside of it is printed in full.
+        assert_eq!(
+            fs::canonicalize(&outside).unwrap(),
+            output_path(&outside, &config)
+        );
+    }
+
+    #[test]
+    fn test_table_group() {
+        // Given two groups, one with a long path,
+        let paths = vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir/b.txt")];
+        let long_paths = vec![
+            PathBuf::from("dir/a-file-with-a-long-name.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+        ];
+
+        // When they are written as a table 50 columns wide,
+        let config = Config::default();
+        let mut table = Table {
+            config: &config,
+            width: Some(50),
+            groups: 0,
+        };
+        let first = write_group(&mut table, &LenHash::from(2048, [0xab; 32]), &paths);
+        let second = write_group(&mut table, &LenHash::from(10, [0x12; 32]), &long_paths);
+
+        // Then the header comes before the first group only, the columns are
+        // aligned, and the long path is cut to fit.
+        assert_eq!(
+            "      SIZE  COUNT  HASH          PATH\n    2.0 kB      2  abababababab  dir/a.txt\n",
+            first
+        );
+        assert_eq!("      10 B      3  121212121212  dir/a-file-wi\n", second);
+    }
+
+    #[test]
+    fn test_originals_only() {
+        // Given a group of three files,
+        let lenhash = LenHash::from(2048, [0xcd; 32]);
+        let paths = vec![
+            PathBuf::from("a.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+        ];
+
+        // and the configuration is to only list the original,
+        let config = Config {
+            originals_only: true,
+            ..Default::default()
+        };
+
+        // When the group is written in each format,
+        // Then only the original is listed, but the human header still
+        // describes the whole group,
+        let human = write_group(
+            &mut Human {
+                config: &config,
+                groups: 0,
+            },
+            &lenhash,
+            &paths,
+        );
+        assert_eq!(
+            format!(
+                "\nSize: 2.0 kB  Wasted: 4.0 kB  Hash: {}\na.txt\n",
+                "cd".repeat(32)
+            ),
+            human
+        );
+        let ndjson = write_group(&mut Ndjson { config: &config }, &lenhash, &paths);
+        assert!(
+            ndjson.contains("\"paths\":[\"a.txt\"],\"modified\":[null]"),
+            "{}",
+            ndjson
+        );
+
+        // and the formats that end each group don't, so there is one path
+        // per line, or per NUL.
+        let fdupes = write_group(&mut Fdupes { config: &config }, &lenhash, &paths);
+        assert_eq!("a.txt\n", fdupes);
+        let null = write_group(&mut NullGroups { config: &config }, &lenhash, &paths);
+        assert_eq!("a.txt\0", null);
+    }
+
+    #[test]
+    fn test_sqlite_group() {
+        // Given a group of two files,
+        let lenhash = LenHash::from(10, [0x34; 32]);
+        let paths = vec![PathBuf::from("dir/a.txt"), PathBuf::from("dir/b.txt")];
+
+        // When the group is given to the sqlite formatter,
+        let out = write_group(&mut Sqlite, &lenhash, &paths);
+
+        // Then nothing is written, since the catalog is written separately.
+        assert_eq!("", out);
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// Reads a list of paths separated by NUL bytes, as written by `find -print0`,
+// from the file, or from stdin if the file is "-".
+pub fn read_null_separated(file: &Path) -> io::Result<Vec<PathBuf>> {
+    let bytes = if file == Path::new("-") {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(file)?
+    };
+    Ok(parse_null_separated(&bytes))
+}
+
+// Splits the bytes into paths at each NUL. Empty paths, such as after the
+// last NUL, are left out.
+pub fn parse_null_separated(bytes: &[u8]) -> Vec<PathBuf> {
+    bytes
+        .split(|b| *b == 0)
+        .filter(|path| !path.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+// Reads glob patterns from the file, one per line. Blank lines, and lines
+// starting with # as comments, are left out.
+pub fn read_patterns(file: &Path) -> io::Result<Vec<String>> {
+    Ok(parse_patterns(&fs::read_to_string(file)?))
+}
+
+pub fn parse_patterns(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+// Expands each glob pattern, such as photos/2023-*, into the paths it
+// matches, in order. Patterns that match nothing are an error, listing all
+// of them, since a scan without them would quietly be missing files.
+pub fn expand_globs(patterns: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    let mut unmatched = Vec::new();
+    for pattern in patterns {
+        let matches = glob::glob(pattern).map_err(|e| format!("{}: {}", pattern, e))?;
+        let before = paths.len();
+        for path in matches {
+            paths.push(path.map_err(|e| e.to_string())?);
+        }
+        if paths.len() == before {
+            unmatched.push(*pattern);
+        }
+    }
+    if !unmatched.is_empty() {
+        return Err(format!("nothing matches {}", unmatched.join(", ")));
+    }
+    Ok(paths)
+}
+
+// Paths on Unix are any bytes, so they are kept as they are, even if they
+// aren't valid UTF-8.
+#[cfg(target_family = "unix")]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_null_separated() {
+        let paths = parse_null_separated(b"a.txt\0dir/with\nnewline.txt\0\0");
+        assert_eq!(
+            vec![
+                PathBuf::from("a.txt"),
+                PathBuf::from("dir/with\nnewline.txt")
+            ],
+            paths
+        );
+    }
+
+    #[test]
+    fn test_parse_patterns() {
+        let patterns = parse_patterns("# Build output\n*.o\n\n  target  \r\n#*.rs\n");
+        assert_eq!(vec!["*.o".to_string(), "target".to_string()], patterns);
+    }
+
+    #[test]
+    fn test_expand_globs() {
+        let dir = Path::new("target/test_dir/expand_globs");
+        fs::create_dir_all(dir.join("2023-01")).unwrap();
+        fs::create_dir_all(dir.join("2023-02")).unwrap();
+        fs::create_dir_all(dir.join("2024-01")).unwrap();
+
+        let pattern = format!("{}/2023-*", dir.display());
+        let paths = expand_globs(&[&pattern]).expect("Could not expand pattern.");
+        assert_eq!(vec![dir.join("2023-01"), dir.join("2023-02")], paths);
+
+        let missing = format!("{}/1999-*", dir.display());
+        let err = expand_globs(&[&pattern, &missing]).unwrap_err();
+        assert!(
+            err.ends_with(&format!("nothing matches {}", missing)),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_parse_null_separated_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let paths = parse_null_separated(b"caf\xe9.txt\0b.txt");
+        assert_eq!(2, paths.len());
+        assert_eq!(b"caf\xe9.txt", paths[0].as_os_str().as_bytes());
+        assert!(paths[0].to_str().is_none());
+    }
+}
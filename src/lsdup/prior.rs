@@ -0,0 +1,401 @@
+use crate::lsdup::base64;
+use crate::lsdup::lenhash::LenHash;
+use crate::lsdup::output;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The hashes of the files listed by an earlier --format ndjson result, given
+// with --since, so that files which haven't changed since don't need to be
+// hashed again. Only files that were in a group are listed in the result, so
+// only their hashes can be reused.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct PriorScan {
+    files: BTreeMap<PathBuf, PriorFile>,
+}
+
+// The modification time a file had when it was hashed, and its hash.
+#[derive(std::fmt::Debug)]
+struct PriorFile {
+    modified: u64,
+    hash: LenHash,
+}
+
+impl PriorScan {
+    // Number of files with a hash that could be reused.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    // The hash the file had before, if it still has the same size and
+    // modification time. Files that can't be read, or are gone, have none.
+    pub fn hash_of(&self, file: &Path, size: u64) -> Option<LenHash> {
+        let prior = self.files.get(file)?;
+        if prior.hash.len() != size || modified_nanos(file) != Some(prior.modified) {
+            return None;
+        }
+        Some(prior.hash)
+    }
+}
+
+// The groups listed by an earlier --format ndjson result, given with
+// --groups-from, to compare with the groups found now.
+#[derive(std::fmt::Debug, std::default::Default)]
+pub struct PriorGroups {
+    groups: BTreeMap<LenHash, Vec<PathBuf>>,
+}
+
+impl PriorGroups {
+    // Number of groups in the earlier result.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    // True if the earlier result had a group of files with this content.
+    pub fn contains(&self, lenhash: &LenHash) -> bool {
+        self.groups.contains_key(&lenhash.without_extra())
+    }
+
+    // Each group of the earlier result, with its files as they were listed.
+    pub fn iter(&self) -> impl Iterator<Item = (&LenHash, &Vec<PathBuf>)> {
+        self.groups.iter()
+    }
+}
+
+// Reads the earlier result from the file.
+pub fn load(file: &Path) -> io::Result<PriorScan> {
+    let text = fs::read_to_string(file)?;
+    parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Reads the groups of the earlier result from the file.
+pub fn load_groups(file: &Path) -> io::Result<PriorGroups> {
+    let text = fs::read_to_string(file)?;
+    parse_groups(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Reads the files of each group from ndjson output. The summary line is
+// skipped, as are groups of --quick scans, since those hashes are only of the
+// first bytes. Paths without a modification time can't be checked for
+// changes, so they are left out.
+pub fn parse(text: &str) -> Result<PriorScan, String> {
+    let mut prior = PriorScan::default();
+    for_each_group(text, |hash, group| {
+        let (paths, modified) = match (
+            group_paths(group),
+            group.get("modified").and_then(Json::as_array),
+        ) {
+            (Some(paths), Some(modified)) => (paths, modified),
+            _ => return,
+        };
+        for (path, modified) in paths.into_iter().zip(modified) {
+            if let (Some(path), Some(modified)) = (path, modified.as_u64()) {
+                prior.files.insert(path, PriorFile { modified, hash });
+            }
+        }
+    })?;
+    Ok(prior)
+}
+
+// Reads each group from ndjson output, skipping the same lines as parse.
+pub fn parse_groups(text: &str) -> Result<PriorGroups, String> {
+    let mut prior = PriorGroups::default();
+    for_each_group(text, |hash, group| {
+        if let Some(paths) = group_paths(group) {
+            prior
+                .groups
+                .insert(hash, paths.into_iter().flatten().collect());
+        }
+    })?;
+    Ok(prior)
+}
+
+// The paths of the group, decoded if they were written with --path-encoding
+// base64. A path that can't be read is None, so that the rest still line up
+// with their modification times.
+fn group_paths(group: &Json) -> Option<Vec<Option<PathBuf>>> {
+    let paths = group.get("paths").and_then(Json::as_array)?;
+    let base64 = group.get("path_encoding").and_then(Json::as_str) == Some("base64");
+    let paths = paths.iter().map(|path| {
+        let path = path.as_str()?;
+        if !base64 {
+            return Some(PathBuf::from(path));
+        }
+        base64::decode(path).ok().map(output::path_from_bytes)
+    });
+    Some(paths.collect())
+}
+
+// Calls each_group with the hash and the JSON of every verified group.
+fn for_each_group(text: &str, mut each_group: impl FnMut(LenHash, &Json)) -> Result<(), String> {
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let group = Json::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let (size, hash) = match (
+            group.get("size").and_then(Json::as_u64),
+            group.get("hash").and_then(Json::as_str),
+        ) {
+            (Some(size), Some(hash)) => (size, hash),
+            _ => continue,
+        };
+        if let Some(Json::Bool(false)) = group.get("verified") {
+            continue;
+        }
+        let hash = LenHash::from_hex(size, hash)
+            .ok_or_else(|| format!("line {}: {:?} is not a hash", i + 1, hash))?;
+        each_group(hash, &group);
+    }
+    Ok(())
+}
+
+// The modification time of the file in nanoseconds since the Unix epoch, as
+// written in the ndjson output. None if it can't be read.
+pub fn modified_nanos(file: &Path) -> Option<u64> {
+    nanos(file.metadata().and_then(|meta| meta.modified()).ok()?)
+}
+
+// The time in nanoseconds since the Unix epoch, or None if it is before it.
+pub fn nanos(time: SystemTime) -> Option<u64> {
+    let nanos = time.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    u64::try_from(nanos).ok()
+}
+
+// Just enough of JSON to read back the ndjson output. Numbers are kept as
+// written, so that large whole numbers aren't rounded.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            chars: text.chars().peekable(),
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some(c) => Err(format!("unexpected {:?} after the value", c)),
+            None => Ok(value),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected {:?} but found {:?}", expected, c)),
+            None => Err(format!("expected {:?} but the line ended", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Json::String),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    number.push(c);
+                }
+                Ok(Json::Number(number))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(format!("unexpected {:?}", word)),
+                }
+            }
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("expected a value but the line ended".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err("expected , or } in an object".to_string()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(values)),
+                _ => return Err("expected , or ] in an array".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER);
+                        s.push(c);
+                    }
+                    _ => return Err("bad escape in a string".to_string()),
+                },
+                Some(c) => s.push(c),
+                None => return Err("a string was not closed".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = format!(
+            "{{\"size\":10,\"hash\":\"{}\",\"paths\":[\"a.txt\",\"dir/\\\"b\\\"\\\\\\n.txt\",\"c.txt\"],\"modified\":[1700000000123456789,5,null]}}\n\
+             {{\"size\":20,\"hash\":\"{}\",\"verified\":false,\"paths\":[\"d.txt\"],\"modified\":[5]}}\n\
+             {{\"summary\":{{\"files\":3,\"bytes\":30,\"duplicates\":2,\"wasted_bytes\":20,\"groups\":1}}}}\n",
+            "ab".repeat(32),
+            "cd".repeat(32)
+        );
+
+        let prior = parse(&text).expect("Could not parse result.");
+
+        // Files without a modification time, and unverified groups, are left out.
+        assert_eq!(2, prior.len());
+        let a = &prior.files[Path::new("a.txt")];
+        assert_eq!(1700000000123456789, a.modified);
+        assert_eq!(LenHash::from(10, [0xab; 32]), a.hash);
+        assert!(prior.files.contains_key(Path::new("dir/\"b\"\\\n.txt")));
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let text = format!(
+            "{{\"size\":10,\"hash\":\"{}\",\"paths\":[\"a.txt\",\"b.txt\"],\"modified\":[null,null]}}\n\
+             {{\"size\":20,\"hash\":\"{}\",\"verified\":false,\"paths\":[\"d.txt\",\"e.txt\"],\"modified\":[5,5]}}\n\
+             {{\"summary\":{{\"files\":2,\"bytes\":20,\"duplicates\":1,\"wasted_bytes\":10,\"groups\":1}}}}\n",
+            "ab".repeat(32),
+            "cd".repeat(32)
+        );
+
+        let prior = parse_groups(&text).expect("Could not parse result.");
+
+        // Unverified groups, and the summary, are left out.
+        assert_eq!(1, prior.len());
+        let lenhash = LenHash::from(10, [0xab; 32]);
+        assert!(prior.contains(&lenhash));
+        assert!(prior.contains(&LenHash::with_extra(10, [0xab; 32], [0xef; 32])));
+        let (_, paths) = prior.iter().next().unwrap();
+        assert_eq!(&vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")], paths);
+    }
+
+    #[test]
+    fn test_parse_bad_line() {
+        let err = parse("{\"size\":10,\n").unwrap_err();
+        assert!(err.starts_with("line 1:"), "{}", err);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::lsdup::config::Config;
+use std::fmt;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Why a file or directory was left out of the scan.
+#[derive(std::fmt::Debug)]
+pub enum Skip {
+    // Not a directory or regular file, such as a socket or device.
+    NotRegular,
+    // A symlink, and symlinks aren't being followed.
+    Symlink,
+    // A symlink to something that doesn't exist.
+    BrokenSymlink,
+    // Hidden, and hidden files and directories are skipped.
+    Hidden,
+    // A directory that was already read, reached again through a symlink, or
+    // with --case-fold, a path already reached spelled with other case.
+    AlreadyVisited,
+    // Its modification time couldn't be read, to compare with the window.
+    NoModifiedTime(io::Error),
+    // Not one of the --extensions.
+    Extension,
+    // Modified outside of the --modified-after and --modified-before window.
+    OutsideModifiedWindow,
+    // Larger than --max-size.
+    TooLarge,
+    // Smaller than --min-size, such as an empty file.
+    TooSmall,
+    // A hard link to data already seen through another link.
+    HardLinkSeen,
+    // Matches an --exclude pattern.
+    Excluded,
+    // Its size wasn't picked for the --sample.
+    NotSampled,
+    // Its size is within an --exclude-size-range.
+    ExcludedSize,
+    // Removed after its directory was read, but before it was looked at, as
+    // happens when scanning files that are in use.
+    Vanished,
+}
+
+impl Skip {
+    // The verbosity at which the skip is logged. Files that most likely
+    // weren't meant to be skipped are always mentioned, while reasons that
+    // apply to many files only are at -vvv. Special files are counted in the
+    // summary instead, so each is only mentioned at -v.
+    fn verbosity(&self) -> u8 {
+        match self {
+            Skip::Symlink => 0,
+            Skip::NotRegular
+            | Skip::BrokenSymlink
+            | Skip::Hidden
+            | Skip::AlreadyVisited
+            | Skip::NoModifiedTime(_) => 1,
+            Skip::Extension
+            | Skip::OutsideModifiedWindow
+            | Skip::TooLarge
+            | Skip::TooSmall
+            | Skip::HardLinkSeen
+            | Skip::Excluded
+            | Skip::NotSampled
+            | Skip::ExcludedSize
+            | Skip::Vanished => 3,
+        }
+    }
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Skip::NotRegular => write!(f, "not a directory or regular file"),
+            Skip::Symlink => write!(f, "symlink not followed"),
+            Skip::BrokenSymlink => write!(f, "broken symlink"),
+            Skip::Hidden => write!(f, "hidden"),
+            Skip::AlreadyVisited => write!(f, "already visited"),
+            Skip::NoModifiedTime(e) => write!(f, "no modification time: {}", e),
+            Skip::Extension => write!(f, "extension not in --extensions"),
+            Skip::OutsideModifiedWindow => write!(f, "modified outside of the time window"),
+            Skip::TooLarge => write!(f, "larger than --max-size"),
+            Skip::TooSmall => write!(f, "smaller than --min-size"),
+            Skip::HardLinkSeen => write!(f, "hard link to a file already seen"),
+            Skip::Excluded => write!(f, "matches --exclude"),
+            Skip::NotSampled => write!(f, "size not in the --sample"),
+            Skip::ExcludedSize => write!(f, "size within --exclude-size-range"),
+            Skip::Vanished => write!(f, "removed while scanning"),
+        }
+    }
+}
+
+// How many special files, that aren't directories, regular files, or
+// symlinks, were skipped, by what they are.
+#[derive(std::fmt::Debug, std::default::Default, std::clone::Clone, std::marker::Copy)]
+pub struct SpecialFiles {
+    pub fifos: u32,
+    pub sockets: u32,
+    // Block and character devices.
+    pub devices: u32,
+    // Anything else, on platforms where it can't be told what it is.
+    pub other: u32,
+}
+
+impl SpecialFiles {
+    // Counts the file by its type.
+    pub fn add(&mut self, metadata: &Metadata) {
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::FileTypeExt;
+
+            let file_type = metadata.file_type();
+            if file_type.is_fifo() {
+                self.fifos += 1;
+            } else if file_type.is_socket() {
+                self.sockets += 1;
+            } else if file_type.is_block_device() || file_type.is_char_device() {
+                self.devices += 1;
+            } else {
+                self.other += 1;
+            }
+        }
+        #[cfg(not(target_family = "unix"))]
+        {
+            let _ = metadata;
+            self.other += 1;
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.fifos + self.sockets + self.devices + self.other
+    }
+}
+
+// Lists the kinds counted, such as "2 fifos, 1 socket".
+impl fmt::Display for SpecialFiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds = [
+            (self.fifos, "fifo", "fifos"),
+            (self.sockets, "socket", "sockets"),
+            (self.devices, "device", "devices"),
+            (self.other, "other", "other"),
+        ];
+        let mut first = true;
+        for (count, one, many) in kinds.iter().filter(|kind| kind.0 > 0) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(f, "{} {}", count, if *count == 1 { one } else { many })?;
+        }
+        Ok(())
+    }
+}
+
+// Logs that the path was skipped and why, if the verbosity is high enough
+// for that reason.
+pub fn log(verbosity: u8, path: &Path, skip: Skip) {
+    if verbosity >= skip.verbosity() {
+        eprintln!("Skipping {:?}: {}.", path, skip);
+    }
+}
+
+// True if the whole path, or just the name, matches any of the --exclude
+// patterns, or the path within the directory being scanned matches any of the
+// anchored ones.
+pub fn is_excluded(path: &Path, config: &Config) -> bool {
+    let excluded = config
+        .excludes
+        .iter()
+        .any(|pattern| pattern.matches_path(path))
+        || is_name_excluded(path, config);
+    if excluded || config.anchored_excludes.is_empty() {
+        return excluded;
+    }
+    // As in .gitignore, a * or ? of an anchored pattern stays within one
+    // directory.
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    within_root(path, &config.dirs).is_some_and(|within| {
+        config
+            .anchored_excludes
+            .iter()
+            .any(|pattern| pattern.matches_path_with(within, options))
+    })
+}
+
+// True if just the name of the path matches any of the --exclude patterns.
+// Entries within archives are checked by their name within the archive,
+// since their full path names the archive too.
+pub fn is_name_excluded(path: &Path, config: &Config) -> bool {
+    path.file_name().is_some_and(|name| {
+        config
+            .excludes
+            .iter()
+            .any(|pattern| pattern.matches_path(Path::new(name)))
+    })
+}
+
+// The path within the directory it was found under. If the directories given
+// are nested, it is within the innermost of them.
+fn within_root<'a>(path: &'a Path, roots: &[PathBuf]) -> Option<&'a Path> {
+    roots
+        .iter()
+        .filter_map(|root| path.strip_prefix(root).ok())
+        .min_by_key(|within| within.components().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity() {
+        // Symlinks that aren't followed are always mentioned, as before,
+        assert_eq!(0, Skip::Symlink.verbosity());
+        assert_eq!(1, Skip::Hidden.verbosity());
+        // and files left out by the filters only at -vvv, since there can be many.
+        assert_eq!(3, Skip::Extension.verbosity());
+        assert_eq!(
+            "no modification time: gone",
+            Skip::NoModifiedTime(io::Error::other("gone")).to_string()
+        );
+    }
+}
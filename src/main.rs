@@ -13,26 +13,136 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use lsdup::lsdup::config::Config;
+use lsdup::lsdup::action::{act_interactively, act_on_duplicates, write_script, Shell};
+use lsdup::lsdup::catalog::write_catalog;
+use lsdup::lsdup::config::{Config, Format};
+use lsdup::lsdup::output::friendly_bytes;
+
+// Exit codes, so that scripts can tell whether duplicates were found.
+// No duplicate groups were found.
+const EXIT_NO_DUPES: i32 = 0;
+// At least one duplicate group was found.
+const EXIT_DUPES_FOUND: i32 = 1;
+// The arguments were bad or the scan could not be done.
+const EXIT_ERROR: i32 = 2;
+// Interrupted a second time, before any results could be listed.
+const EXIT_INTERRUPTED: i32 = 130;
 
 fn main() {
     let config = Config::new().unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        process::exit(EXIT_ERROR);
     });
 
+    // Open the output first, so a bad path is found before the scan, not after.
+    let mut out: Box<dyn Write> = match &config.output {
+        // The database is opened once the scan is done, rather than
+        // overwritten, so that earlier scans are kept.
+        Some(_) if config.format == Format::Sqlite => Box::new(io::sink()),
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Could not create output file {:?}: {}", path, e);
+                process::exit(EXIT_ERROR);
+            }
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+
     if config.verbosity > 0 {
         eprintln!("Analyzing for {:?}...", config.dirs);
+        eprintln!(
+            "Using {} thread(s) to walk, and {} to read files.",
+            config.threads, config.io_threads
+        );
+    }
+    // The first Ctrl-C stops the scan, and the duplicates found so far are
+    // listed. A second one quits right away, as does any once the scan is
+    // over, so that acting on the duplicates can be stopped.
+    static STOP: AtomicBool = AtomicBool::new(false);
+    static SCANNED: AtomicBool = AtomicBool::new(false);
+    let handler = ctrlc::set_handler(|| {
+        if STOP.swap(true, Ordering::Relaxed) || SCANNED.load(Ordering::Relaxed) {
+            process::exit(EXIT_INTERRUPTED);
+        }
+    });
+    if let Err(e) = handler {
+        eprintln!("Warning: Ctrl-C will quit without listing results: {}", e);
     }
-    match lsdup::run(&config) {
+
+    let result = lsdup::run_until(&config, &STOP);
+    SCANNED.store(true, Ordering::Relaxed);
+    match result {
         Err(e) => {
             eprintln!("Application error: {}", e);
-            process::exit(1);
+            process::exit(EXIT_ERROR);
         }
         Ok(dups) => {
-            lsdup::print_results(&dups);
+            lsdup::print_results(&mut out, &dups, &config);
+            // process::exit doesn't run destructors, so the output is
+            // flushed and closed here rather than when it goes out of scope.
+            if let Err(e) = out.flush() {
+                eprintln!("Error: Could not write results: {}", e);
+                process::exit(EXIT_ERROR);
+            }
+            drop(out);
+            if let (Format::Sqlite, Some(db)) = (config.format, &config.output) {
+                if let Err(e) = write_catalog(&dups, db) {
+                    eprintln!("Error: Could not write catalog {:?}: {}", db, e);
+                    process::exit(EXIT_ERROR);
+                }
+            }
+            // A partial result leaves out files that could have been the
+            // original, so nothing is acted on.
+            if dups.interrupted() && (config.script.is_some() || config.action.is_some()) {
+                eprintln!("Interrupted, so the duplicates were not acted on.");
+            } else if let Some(path) = &config.script {
+                let written = File::create(path).and_then(|file| {
+                    let mut file = BufWriter::new(file);
+                    let report = write_script(&dups, &config, Shell::native(), &mut file)?;
+                    file.flush()?;
+                    Ok(report)
+                });
+                match written {
+                    Ok(report) => eprintln!(
+                        "Wrote a script for {} duplicate files, {} to {:?}.",
+                        report.files,
+                        friendly_bytes(report.bytes),
+                        path
+                    ),
+                    Err(e) => {
+                        eprintln!("Error: Could not write script {:?}: {}", path, e);
+                        process::exit(EXIT_ERROR);
+                    }
+                }
+            } else if config.action.is_some() {
+                let report = if config.interactive {
+                    let mut input = io::stdin().lock();
+                    match act_interactively(&dups, &config, &mut input, &mut io::stdout()) {
+                        Ok(report) => report,
+                        Err(e) => {
+                            eprintln!("Error: Could not ask which files to keep: {}", e);
+                            process::exit(EXIT_ERROR);
+                        }
+                    }
+                } else {
+                    act_on_duplicates(&dups, &config)
+                };
+                lsdup::print_action_report(&report, &config);
+                if report.failed > 0 {
+                    process::exit(EXIT_ERROR);
+                }
+            }
+            if dups.into_iter().next().is_some() {
+                process::exit(EXIT_DUPES_FOUND);
+            }
+            process::exit(EXIT_NO_DUPES);
         }
     }
 }